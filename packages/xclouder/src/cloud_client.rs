@@ -1,84 +1,73 @@
-use std::collections::HashMap;
-use crate::{config::{BucketSource, CloudMagic, CloudSource}, error::{XError, XResult}, strategy::{Strategy, UrlRes}, Config, Native};
+use std::collections::{HashMap, HashSet};
+use crate::{config::{BucketSource, CloudMagic}, error::{XError, XResult}, strategy::{sts_valid, Strategy, UrlRes}, Config, Native, host::UrlFallback};
 use serde_json::Value;
 use crate::events::Emitter;
-use std::sync::Arc;
-use serde::{Serialize, Deserialize};
+use crate::metrics::{MetricEvent, MetricLabels, Metrics};
+use crate::xkey::{LegacyRule, XKey};
+use std::sync::{Arc, RwLock};
+use serde::Serialize;
 use std::sync::Mutex;
 
-pub struct CloudClient<'a> {
-    pub native: Box<dyn Native>,
+// 一次加载得到的配置，进行中的请求持有自己的 Arc，重新加载配置不影响它们
+#[derive(Default)]
+pub struct ConfigSnapshot {
     pub config: Option<Config>,
-    pub local_config: Option<Config>,
-    
-    pub cloud_source_map: HashMap<String, CloudSource>,
     pub cloud_magics_map: HashMap<String, CloudMagic>,
-    pub branch_cloud_source: HashMap<String, HashMap<String, BucketSource>>,
+    // bucket -> cloudName -> 桶配置，上传时 UploadOpts 直接共享这里的 Arc
+    pub branch_cloud_source: HashMap<String, HashMap<String, Arc<BucketSource>>>,
+}
+
+pub struct CloudClient {
+    pub native: Arc<dyn Native>,
+    pub url_fallback: Option<Arc<dyn UrlFallback>>,
+    snapshot: RwLock<Arc<ConfigSnapshot>>,
     pub cloud_strategy_map: HashMap<String, Box<dyn Strategy>>,
-    pub manual_retry_map: Arc<Mutex<HashMap<String, UploadOpts<'a>>>>,
+    pub manual_retry_map: Arc<Mutex<HashMap<String, UploadOpts>>>,
     pub em_upload_end: Emitter, 
     pub em_upload_begin: Emitter,
     pub em_loaded_remote_config: Emitter,
+    pub metrics: Metrics,
+    pub unhealthy_domains: Mutex<HashSet<String>>,
 }
 
-impl<'a> CloudClient<'a> {
-    pub fn new(native: Box<dyn Native>) -> Self {
+impl CloudClient {
+    pub fn new(native: Arc<dyn Native>, url_fallback: Option<Arc<dyn UrlFallback>>, metrics: Metrics) -> Self {
         Self {
             native,
-            config: None,
-            local_config: None,
-            cloud_source_map: HashMap::new(),
-            cloud_magics_map: HashMap::new(),
-            branch_cloud_source: HashMap::new(),
+            url_fallback,
+            snapshot: RwLock::new(Arc::new(ConfigSnapshot::default())),
             cloud_strategy_map: HashMap::new(),
             manual_retry_map: Arc::new(Mutex::new(HashMap::new())),
             em_upload_end: Emitter::new(),
             em_upload_begin: Emitter::new(),
             em_loaded_remote_config: Emitter::new(),
+            metrics,
+            unhealthy_domains: Mutex::new(HashSet::new()),
         }
     }
 
-    pub fn load_strategy(&mut self, strategy: Box<dyn Strategy>) {
+    // 策略与客户端共用同一个 Native
+    pub fn load_strategy(&mut self, mut strategy: Box<dyn Strategy>) {
+        strategy.load_native(self.native.clone());
         let name = strategy.name();
         self.cloud_strategy_map.insert(name.to_string(), strategy);
     }
 
-    pub fn init(&mut self, remote: Option<String>, local_config: Value) {
-        // 实现初始化逻辑
-        self.load_conf(&local_config, &local_config);
+    // 没有可用域名时的兜底地址，宿主未提供时为 None
+    pub fn fallback_url(&self, bucket: &str, key: &str) -> Option<String> {
+        self.url_fallback.as_ref().map(|fallback| fallback.resolve_fallback(bucket, key))
     }
 
-    pub fn current_bucket_source(&self, bucket: &str, cloud_name: &str, auto_feedback: bool) -> XResult<&BucketSource> {
-        let config = self.config.as_ref().ok_or_else(|| XError::InvalidConfig)?;
-        
-        let cloud_source = config.get_cloud_source(cloud_name)
-            .ok_or_else(|| XError::CloudNotFound)?;
-        
-        let mut bucket_source = cloud_source.buckets.iter()
-            .find(|b| b.name == bucket)
-            .ok_or_else(|| XError::BucketNotFound(bucket.to_string()))?;
-                
-        if auto_feedback {
-            if bucket_source.domain.is_none() || 
-               bucket_source.grayscale.map_or(false, |v| !Self::when_percent(v)) {
-                // 切换到 fallback 域名
-                if bucket_source.fallback.is_some() {
-                    if let Some((fallback_source, fallback_bucket)) = config.resolve_fallback(cloud_source, &bucket_source.name) {
-                        bucket_source = fallback_bucket;
-                    }
-                }
-            }
-        }
-        
-        Ok(bucket_source)
+    pub fn init(&self, remote: Option<String>, local_config: Value) -> XResult<()> {
+        // 实现初始化逻辑
+        self.load_conf(&local_config, &local_config)
     }
 
-    pub fn current_branch_cloud_source(&self, bucket: &str) -> XResult<&HashMap<String, BucketSource>> {
-        let branch_cloud_source = self.branch_cloud_source.get(bucket).ok_or_else(|| XError::BucketNotFound(bucket.to_string()))?;
-        Ok(branch_cloud_source)
+    pub fn snapshot(&self) -> XResult<Arc<ConfigSnapshot>> {
+        Ok(self.snapshot.read()?.clone())
     }
-    
-    pub async fn upload_fn(&self, mut opts: UploadOpts<'a>) -> XResult<String> {
+
+    pub async fn upload_fn(&self, mut opts: UploadOpts) -> XResult<String> {
         self.em_upload_begin.emit("upload_begin", serde_json::json!({
             "opts": &opts
         })).await;
@@ -98,18 +87,43 @@ impl<'a> CloudClient<'a> {
 
         println!("[XClouder] uploadFn {:?}", opts);
         
-        let mut bucket_source = opts.bucket_source;
+        let snapshot = self.snapshot()?;
+        let origin = opts.bucket_source.clone();
+        let mut bucket_source: &BucketSource = &origin;
         let mut errors = Vec::new();
         let mut retry_count = 0;
+        let started = std::time::Instant::now();
+        self.metrics.record(bucket_source, MetricEvent::UploadBegin);
+
+        // 配置错误不重试，但和上传失败一样记录，保证开始和结束的计数对得上
+        let mut fatal = None;
 
         loop {
-          let cloud = bucket_source.cloud.as_ref().ok_or_else(|| XError::InvalidConfig)?;
-            let cloud_strategy = self.get_cloud_strategy(cloud)?;
+            let cloud_strategy = match bucket_source.cloud.as_ref()
+                .ok_or(XError::InvalidConfig)
+                .and_then(|cloud| self.get_cloud_strategy(cloud))
+            {
+                Ok(cloud_strategy) => cloud_strategy,
+                Err(err) => {
+                    errors.push(err.clone());
+                    fatal = Some(err);
+                    break;
+                }
+            };
 
-            match cloud_strategy.get_sts(bucket_source, &opts).await {
+            let sts_hit = self.native.get_storage(&cloud_strategy.storage_key(bucket_source))
+                .is_some_and(|cache| sts_valid(&cache));
+            self.metrics.record(bucket_source, MetricEvent::StsCache { hit: sts_hit });
+
+            match cloud_strategy.get_sts(bucket_source).await {
                 Ok(sts) => {
-                    match cloud_strategy.upload(&bucket_source, sts, &opts).await {
+                    match cloud_strategy.upload(bucket_source, sts, &opts).await {
                         Ok(url_res) => {
+                            let bytes = std::fs::metadata(&opts.file_path).map(|m| m.len()).unwrap_or(0);
+                            self.metrics.record(bucket_source, MetricEvent::UploadSuccess {
+                                bytes,
+                                duration: started.elapsed(),
+                            });
                             self.em_upload_end.emit("upload_end", serde_json::json!({
                                 "opts": &opts,
                                 "url": url_res.to_string()
@@ -126,9 +140,14 @@ impl<'a> CloudClient<'a> {
                                 break;
                             }
 
+                            self.metrics.record(bucket_source, MetricEvent::Retry);
+
                             // 尝试切换域名
                             if retry_count > 3 {
-                                if let Ok(new_source) = self.try_switch_domain(&bucket_source).await {
+                                if let Ok(new_source) = self.try_switch_domain(&snapshot, bucket_source).await {
+                                    self.metrics.record(bucket_source, MetricEvent::Fallback {
+                                        to: MetricLabels::from_bucket_source(new_source),
+                                    });
                                     bucket_source = new_source;
                                     continue;
                                 }
@@ -149,12 +168,16 @@ impl<'a> CloudClient<'a> {
             }
         }
 
-        if opts.manual_retry {
+        if opts.manual_retry && fatal.is_none() {
             let mut retry_map = self.manual_retry_map.lock().map_err(|_| XError::InvalidConfig)?;
             retry_map.insert(opts.file_path.clone(), opts.clone());
         }
 
-        let err = XError::UploadFailed(format!("Upload failed after {} retries", retry_count));
+        let err = fatal.unwrap_or_else(|| XError::UploadFailed(format!("Upload failed after {} retries", retry_count)));
+        self.metrics.record(bucket_source, MetricEvent::UploadFailure {
+            error: errors.last().unwrap_or(&err).class(),
+            duration: started.elapsed(),
+        });
         self.em_upload_end.emit("upload_end", serde_json::json!({
             "opts": opts,
             "error": err.to_string()
@@ -168,54 +191,23 @@ impl<'a> CloudClient<'a> {
             .ok_or_else(|| XError::CloudNotFound)
     }
 
-    pub fn current_cloud_source(&self, cloud_name: &str) -> XResult<Value> {
-        let name = if cloud_name.starts_with('_') {
-            cloud_name
-        } else {
-            &format!("_{}", cloud_name)
-        };
-        
-        self.cloud_source_map.get(name)
-            .cloned()
-            .ok_or_else(|| XError::CloudNotFound)
-            .map(|source| serde_json::to_value(source).unwrap())
-    }
-
-    pub fn load_conf(&mut self, config: &Value, local_config: &Value) {
-        let config = Config::from_json(config.clone()).unwrap_or_else(|_| Config {
-            cloud_source: vec![],
-            cloud_magics: vec![],
-        });
-        let local_config = Config::from_json(local_config.clone()).unwrap_or_else(|_| Config {
-            cloud_source: vec![],
-            cloud_magics: vec![],
-        });
-
-        self.config = Some(config.clone());
-        self.local_config = Some(local_config.clone());
+    pub fn load_conf(&self, config: &Value, local_config: &Value) -> XResult<()> {
+        let config = Config::from_json(config.clone())?;
+        let local_config = Config::from_json(local_config.clone())?;
+        config.validate()?;
+        local_config.validate()?;
 
-        let mut branch_cloud_source = HashMap::new();
+        let mut branch_cloud_source: HashMap<String, HashMap<String, Arc<BucketSource>>> = HashMap::new();
         
         // 加载云源配置
         for source in &config.cloud_source {
             let cloud_name = &source.name;
             
             for bucket in &source.buckets {
-                let bucket_name = &bucket.name;
-                
-                // 添加到 branch_cloud_source
-                if !branch_cloud_source.contains_key(bucket_name) {
-                    branch_cloud_source.insert(bucket_name.to_string(), HashMap::new());
-                }
-                
-                branch_cloud_source.entry(bucket_name.to_string()).or_insert(HashMap::new()).insert(cloud_name.clone(), bucket.clone());
+                branch_cloud_source.entry(bucket.name.to_string()).or_default().insert(cloud_name.clone(), Arc::new(bucket.clone()));
             }
-            
-            self.cloud_source_map.insert(cloud_name.clone(), source.clone());
         }
         
-        self.branch_cloud_source = branch_cloud_source;
-        
         // 加载魔法参数
         let mut cloud_magics_map = HashMap::new();
         for magic in &local_config.cloud_magics {
@@ -224,16 +216,13 @@ impl<'a> CloudClient<'a> {
         for magic in &config.cloud_magics {
             cloud_magics_map.insert(magic.name.clone(), magic.clone());
         }
-        self.cloud_magics_map = cloud_magics_map;
-    }
 
-    pub fn get_bucket_from_source(&self, source: &Value, bucket: &str) -> XResult<Value> {
-        if let Some(buckets) = source["bucketMap"].as_object() {
-            if let Some(bucket_source) = buckets.get(bucket) {
-                return Ok(bucket_source.clone());
-            }
-        }
-        Err(XError::BucketNotFound(bucket.to_string()))
+        *self.snapshot.write()? = Arc::new(ConfigSnapshot {
+            config: Some(config),
+            cloud_magics_map,
+            branch_cloud_source,
+        });
+        Ok(())
     }
 
     pub fn is_xclouder(&self, key: &str) -> bool {
@@ -241,35 +230,62 @@ impl<'a> CloudClient<'a> {
     }
 
     pub fn take_cloud(&self, key: &str) -> Option<String> {
-        if key.starts_with("/_") {
-            let key = &key[1..];
-            return self.take_cloud(key);
-        }
-        
-        if key.starts_with('_') {
-            if let Some(idx) = key.find('/') {
-                return Some(key[..idx].to_string());
-            }
-        }
-        None
+        XKey::parse(key).ok()?.cloud().map(|cloud| cloud.to_string())
     }
 
     pub fn simple_key(&self, key: &str) -> String {
-        if key.starts_with("/_") {
-            return self.simple_key(&key[1..]);
+        XKey::parse(key).map(|xkey| xkey.simple()).unwrap_or_else(|_| key.to_string())
+    }
+
+    pub fn dedup_storage_key(bucket_source: &BucketSource, hash: &str) -> String {
+        format!("dedup:{}:{}:{}",
+            bucket_source.cloud_name.as_deref().unwrap_or(""),
+            bucket_source.name,
+            hash
+        )
+    }
+
+    // 命中本地缓存直接返回地址，不再发 HEAD；缓存在通过 Clouder 删除对象时清掉。
+    // 没有缓存时询问策略，exists 失败（网络错误、策略不支持 HEAD）当作不存在，照常上传
+    pub async fn find_existing(&self, bucket_source: &BucketSource, key: &str, hash: &str) -> XResult<Option<String>> {
+        let storage_key = Self::dedup_storage_key(bucket_source, hash);
+        if let Some(url) = self.native.get_storage(&storage_key).and_then(|v| v.as_str().map(|s| s.to_string())) {
+            return Ok(Some(url));
         }
-        
-        if key.starts_with('_') {
-            if let Some(idx) = key.find('/') {
-                return key[idx + 1..].to_string();
-            }
+
+        let cloud = bucket_source.cloud.as_ref().ok_or(XError::InvalidConfig)?;
+        let cloud_strategy = self.get_cloud_strategy(cloud)?;
+        if !cloud_strategy.exists(bucket_source, key).await.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let url = UrlRes {
+            base_url: bucket_source.base_url(bucket_source.domain.as_deref().unwrap_or("")),
+            key: key.to_string(),
+            domain: bucket_source.domain.clone().unwrap_or_default(),
+            bucket: bucket_source.name.clone(),
+        }.to_string();
+        self.native.set_storage(&storage_key, Value::String(url.clone()));
+        Ok(Some(url))
+    }
+
+    // 去重上传的 key 形如 {cloud}/{hash}{ext}，删除这类对象时一并清掉缓存的地址
+    pub fn forget_dedup(&self, bucket_source: &BucketSource, key: &str) {
+        let name = key.rsplit('/').next().unwrap_or(key);
+        let hash = name.split_once('.').map(|(stem, _)| stem).unwrap_or(name);
+        if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            self.native.del_storage(&Self::dedup_storage_key(bucket_source, hash));
         }
-        key.to_string()
     }
 
-    async fn try_switch_domain(&self, bucket_source: &BucketSource) -> XResult<&BucketSource> {
+    pub fn bucket_strategy(&self, bucket_source: &BucketSource) -> XResult<&dyn Strategy> {
+        let cloud = bucket_source.cloud.as_deref().ok_or(XError::InvalidConfig)?;
+        Ok(self.get_cloud_strategy(cloud)?.as_ref())
+    }
+
+    async fn try_switch_domain<'s>(&self, snapshot: &'s ConfigSnapshot, bucket_source: &BucketSource) -> XResult<&'s BucketSource> {
         // 获取所有可用的备用名
-        let sources = self.feedback_bucket_sources(bucket_source, &[bucket_source])?;
+        let sources = snapshot.feedback_bucket_sources(bucket_source, &[bucket_source])?;
         
         // 检查每个域名的可用性
         for source in sources {
@@ -283,7 +299,62 @@ impl<'a> CloudClient<'a> {
         Err(XError::NetworkError("No available domain".to_string()))
     }
 
-    fn feedback_bucket_sources(&self, bucket_source: &BucketSource, ignore: &[&BucketSource]) -> XResult<Vec<&BucketSource>> {
+}
+
+impl ConfigSnapshot {
+    pub fn current_bucket_source(&self, bucket: &str, cloud_name: &str, auto_feedback: bool) -> XResult<&Arc<BucketSource>> {
+        let config = self.config.as_ref().ok_or_else(|| XError::InvalidConfig)?;
+        
+        let cloud_source = config.get_cloud_source(cloud_name)
+            .ok_or_else(|| XError::CloudNotFound)?;
+        
+        let mut bucket_source = cloud_source.buckets.iter()
+            .find(|b| b.name == bucket)
+            .ok_or_else(|| XError::BucketNotFound(bucket.to_string()))?;
+        let mut source_name = cloud_name;
+                
+        if auto_feedback {
+            if bucket_source.domain.is_none() || 
+               bucket_source.grayscale.map_or(false, |v| !Self::when_percent(v)) {
+                // 切换到 fallback 域名
+                if bucket_source.fallback.is_some() {
+                    if let Some((fallback_source, fallback_bucket)) = config.resolve_fallback(cloud_source, &bucket_source.name) {
+                        bucket_source = fallback_bucket;
+                        source_name = &fallback_source.name;
+                    }
+                }
+            }
+        }
+        
+        self.branch_cloud_source.get(&bucket_source.name)
+            .and_then(|sources| sources.get(source_name))
+            .ok_or_else(|| XError::BucketNotFound(bucket_source.name.clone()))
+    }
+
+    pub fn current_branch_cloud_source(&self, bucket: &str) -> XResult<&HashMap<String, Arc<BucketSource>>> {
+        let branch_cloud_source = self.branch_cloud_source.get(bucket).ok_or_else(|| XError::BucketNotFound(bucket.to_string()))?;
+        Ok(branch_cloud_source)
+    }
+    
+    pub fn cloud_bucket_source(&self, bucket: &str, cloud_name: &str) -> XResult<&BucketSource> {
+        self.current_branch_cloud_source(bucket)?
+            .get(cloud_name)
+            .map(Arc::as_ref)
+            .ok_or(XError::CloudNotFound)
+    }
+
+    pub fn legacy_rules(&self) -> &[LegacyRule] {
+        self.config.as_ref().map(|config| config.legacy_rules.as_slice()).unwrap_or(&[])
+    }
+
+    // 按 key 的云源前缀找到桶配置，旧格式 key 走 legacyRules
+    pub fn key_bucket_source(&self, bucket: &str, key: &str) -> XResult<(&BucketSource, String)> {
+        let xkey = XKey::parse(key)?;
+        let bucket_source = self.cloud_bucket_source(bucket, xkey.cloud_name(self.legacy_rules()))?;
+        Ok((bucket_source, xkey.object_key()))
+    }
+
+    pub fn feedback_bucket_sources(&self, bucket_source: &BucketSource, ignore: &[&BucketSource]) -> XResult<Vec<&BucketSource>> {
         let mut sources = Vec::new();
         let mut current = bucket_source;
         
@@ -291,7 +362,7 @@ impl<'a> CloudClient<'a> {
             let (cloud_name, bucket_name) = self.parse_fallback(&fallback);
             let bucket_name = bucket_name.unwrap_or_else(|| &current.name);
             
-            if let Ok(source) = self.current_bucket_source(bucket_name, cloud_name, false) {
+            if let Ok(source) = self.current_bucket_source(bucket_name, cloud_name, false).map(Arc::as_ref) {
                 if !ignore.contains(&source) && !sources.contains(&source) {
                     sources.push(source);
                     current = source;
@@ -309,9 +380,13 @@ impl<'a> CloudClient<'a> {
             .unwrap_or((fallback, None))
     }
 
-    pub fn current_magics(&self, magics: &[&str]) -> XResult<Vec<&CloudMagic>> {
-        let magics = magics.iter().map(|magic| self.cloud_magics_map.get(*magic).ok_or_else(|| XError::InvalidConfig)).collect::<Result<Vec<_>, _>>()?;
-        Ok(magics)
+    pub fn current_magics(&self, magics: &[&str]) -> XResult<Vec<CloudMagic>> {
+        magics.iter().map(|magic| {
+            let (name, args) = crate::resolver::magic::parse_call(magic)?;
+            let cloud_magic = self.cloud_magics_map.get(name)
+                .ok_or_else(|| XError::MagicError(format!("magic {} not found", name)))?;
+            cloud_magic.render(&args)
+        }).collect()
     }
 
     fn when_percent(scale: i64) -> bool {
@@ -321,8 +396,8 @@ impl<'a> CloudClient<'a> {
 }
 
 #[derive(Clone)]
-pub struct UploadOpts<'a> {
-    pub bucket_source: &'a BucketSource,
+pub struct UploadOpts {
+    pub bucket_source: Arc<BucketSource>,
     pub bucket: String,
     pub filename: String,
     pub file_path: String,
@@ -333,14 +408,14 @@ pub struct UploadOpts<'a> {
     pub manual_retry: bool,
 }
 
-impl<'a> Serialize for UploadOpts<'a> {
+impl Serialize for UploadOpts {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         use serde::ser::SerializeStruct;
         let mut state = serializer.serialize_struct("UploadParams", 8)?;
-        state.serialize_field("bucket_source", &self.bucket_source)?;
+        state.serialize_field("bucket_source", &*self.bucket_source)?;
         state.serialize_field("bucket", &self.bucket)?;
         state.serialize_field("filename", &self.filename)?;
         state.serialize_field("file_path", &self.file_path)?;
@@ -352,7 +427,7 @@ impl<'a> Serialize for UploadOpts<'a> {
     }
 }
 
-impl<'a> std::fmt::Debug for UploadOpts<'a> {
+impl std::fmt::Debug for UploadOpts {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UploadParams")
             .field("bucket_source", &self.bucket_source)
@@ -1,13 +1,18 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use serde_json::Value;
+use crate::error::XResult;
+use crate::key_template;
+use crate::xkey::LegacyRule;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(rename = "cloudSource")]
     pub cloud_source: Vec<CloudSource>,
     #[serde(rename = "cloudMagics")]
     pub cloud_magics: Vec<CloudMagic>,
+    #[serde(rename = "legacyRules", default, skip_serializing_if = "Vec::is_empty")]
+    pub legacy_rules: Vec<LegacyRule>,
 }
 
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
@@ -30,15 +35,53 @@ pub struct BucketSource {
     #[serde(rename = "cloudName")]
     pub cloud_name: Option<String>,
     pub grayscale: Option<i64>,
+    #[serde(rename = "cdnAuth", default, skip_serializing_if = "Option::is_none")]
+    pub cdn_auth: Option<CdnAuth>,
+    #[serde(rename = "keyTemplate", default, skip_serializing_if = "Option::is_none")]
+    pub key_template: Option<String>,
+    // Azure 等按路径区分容器的云，对象地址为 https://{domain}/{container}/{key}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+}
+
+impl BucketSource {
+    // domain 可带协议，如本地存储的 file:///data/img、http://localhost:8080/img
+    pub fn base_url(&self, domain: &str) -> String {
+        let origin = if domain.contains("://") {
+            domain.trim_end_matches('/').to_string()
+        } else {
+            format!("https://{}", domain)
+        };
+        match &self.container {
+            Some(container) => format!("{}/{}", origin, container),
+            None => origin,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct CdnAuth {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub param: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CloudMagic {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<MagicParam>,
     #[serde(rename = "cloudCfg")]
     pub cloud_cfg: HashMap<String, String>,
 }
 
+// 没有 default 的参数为必填
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MagicParam {
+    pub name: String,
+    pub default: Option<String>,
+}
+
 impl CloudMagic {
     pub fn get_magic(&self, cloud: &str) -> Option<&str> {
         self.cloud_cfg.get(cloud).map(|s| s.as_str())
@@ -47,7 +90,9 @@ impl CloudMagic {
 
 impl Config {
     pub fn from_json(value: Value) -> serde_json::Result<Self> {
-        serde_json::from_value(value)
+        let mut config: Config = serde_json::from_value(value)?;
+        config.normalize();
+        Ok(config)
     }
 
     pub fn get_bucket(&self, cloud_name: &str, bucket_name: &str) -> Option<&BucketSource> {
@@ -95,6 +140,42 @@ impl Config {
         }
     }
 
+    // fallback 支持 "_cos.backup-img" 和 "_cos"（同名 bucket）两种写法
+    pub fn fallback_bucket(&self, bucket_source: &BucketSource) -> Option<&BucketSource> {
+        let fallback = bucket_source.fallback.as_deref()?;
+        let (cloud_name, bucket_name) = fallback.split_once('.')
+            .unwrap_or((fallback, bucket_source.name.as_str()));
+        self.get_bucket(cloud_name, bucket_name)
+    }
+
+    // 将云源上的 cloud 下发到 bucket，并记录 bucket 所属的 cloudName
+    pub fn normalize(&mut self) {
+        for source in &mut self.cloud_source {
+            for bucket in &mut source.buckets {
+                if bucket.cloud.is_none() {
+                    bucket.cloud = source.cloud.clone();
+                }
+                if bucket.cloud_name.is_none() {
+                    bucket.cloud_name = Some(source.name.clone());
+                }
+            }
+        }
+    }
+
+    pub fn validate(&self) -> XResult<()> {
+        for source in &self.cloud_source {
+            for bucket in &source.buckets {
+                if let Some(template) = &bucket.key_template {
+                    key_template::validate(template)?;
+                }
+            }
+        }
+        for magic in &self.cloud_magics {
+            magic.validate()?;
+        }
+        Ok(())
+    }
+
     pub fn get_bucket_domain(&self, cloud_name: &str, bucket_name: &str) -> Option<&str> {
         self.get_bucket(cloud_name, bucket_name)
             .and_then(|bucket| bucket.domain.as_deref())
@@ -129,6 +210,7 @@ impl Config {
                         existing_bucket.domain = bucket.domain.clone();
                         existing_bucket.cdn_domain = bucket.cdn_domain.clone();
                         existing_bucket.fallback = bucket.fallback.clone();
+                        existing_bucket.key_template = bucket.key_template.clone();
                     } else {
                         existing.buckets.push(bucket.clone());
                     }
@@ -138,11 +220,20 @@ impl Config {
             }
         }
 
+        for rule in &other.legacy_rules {
+            if !self.legacy_rules.contains(rule) {
+                self.legacy_rules.push(rule.clone());
+            }
+        }
+
         // 合并魔法参数配置
         for magic in &other.cloud_magics {
             if let Some(existing) = self.cloud_magics.iter_mut()
                 .find(|m| m.name == magic.name) {
                 existing.cloud_cfg.extend(magic.cloud_cfg.clone());
+                if !magic.params.is_empty() {
+                    existing.params = magic.params.clone();
+                }
             } else {
                 self.cloud_magics.push(magic.clone());
             }
@@ -208,4 +299,18 @@ mod tests {
         assert_eq!(fallback_source.name, "_cos");
         assert_eq!(fallback_bucket.name, "backup-img");
     }
+
+    #[test]
+    fn test_validate_magics() {
+        let config = |template: &str| Config::from_json(serde_json::json!({
+            "cloudSource": [],
+            "cloudMagics": [{
+                "name": "thumbnail",
+                "params": [{ "name": "w" }],
+                "cloudCfg": { "cos": template }
+            }]
+        })).unwrap();
+        assert!(config("imageMogr2/thumbnail/{w}x").validate().is_ok());
+        assert!(config("imageMogr2/thumbnail/{w}x{h}").validate().is_err());
+    }
 } 
\ No newline at end of file
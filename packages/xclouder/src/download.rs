@@ -0,0 +1,300 @@
+use std::sync::Arc;
+use crate::error::{XError, XResult};
+use crate::strategy::ObjectMeta;
+use crate::utils::{file_md5, plain_md5};
+use crate::{resolver, Clouder, DownloadArgs, RequestArgs, XKey};
+
+pub struct DownloadOptions {
+    pub on_progress: Option<Arc<dyn Fn(f32) + Send + Sync>>,
+    // 下载完成后比对大小和 MD5 ETag
+    pub verify: bool,
+}
+
+// 记录未完成下载对应的 ETag，对象变化后不能续传
+fn state_key(dest: &str) -> String {
+    format!("download:{}", dest)
+}
+
+fn check(file_path: &str, meta: &ObjectMeta) -> XResult<()> {
+    let size = std::fs::metadata(file_path)
+        .map_err(|e| XError::RequestFailed(format!("read {}: {}", file_path, e)))?
+        .len();
+    if size != meta.size {
+        return Err(XError::ChecksumMismatch(format!("{} size {} != {}", meta.key, size, meta.size)));
+    }
+    if let Some(expected) = plain_md5(meta.etag.as_deref()) {
+        let actual = file_md5(file_path)?;
+        if actual != expected {
+            return Err(XError::ChecksumMismatch(format!("{} md5 {} != {}", meta.key, actual, expected)));
+        }
+    }
+    Ok(())
+}
+
+impl Clouder {
+    // 依次尝试 cdnDomain、源站和 fallback 链上的域名，未完成的部分保存在 dest.part 供续传。
+    // 请求不带签名，只支持公有读的 bucket；私有 bucket 请用 resolve_signed 生成地址
+    pub async fn download(&self, bucket: &str, key: &str, dest: &str, opts: DownloadOptions) -> XResult<ObjectMeta> {
+        let mut last_err = XError::NoAvailableDomain(key.to_string());
+        for url in self.download_urls(bucket, key)? {
+            match self.download_from(&url, key, dest, &opts).await {
+                Ok(meta) => return Ok(meta),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(last_err)
+    }
+
+    fn download_urls(&self, bucket: &str, key: &str) -> XResult<Vec<String>> {
+        let snapshot = self.client.snapshot()?;
+        let branch_cloud_source = snapshot.current_branch_cloud_source(bucket)?;
+        let unhealthy_domains = self.client.unhealthy_domains.lock()?;
+        let urls = resolver::resolve_candidates(branch_cloud_source, key, &resolver::ResolveOptions {
+            config: snapshot.config.as_ref(),
+            unhealthy_domains: Some(&unhealthy_domains),
+            clouds: None,
+        })?;
+        if urls.is_empty() {
+            return self.client.fallback_url(bucket, key)
+                .map(|url| vec![url])
+                .ok_or_else(|| XError::NoAvailableDomain(key.to_string()));
+        }
+        Ok(urls)
+    }
+
+    async fn download_from(&self, url: &str, key: &str, dest: &str, opts: &DownloadOptions) -> XResult<ObjectMeta> {
+        let native = &self.client.native;
+        let headers = native.request(RequestArgs {
+            method: "HEAD".to_string(),
+            url: url.to_string(),
+            enable_cache: false,
+            timeout: 10000,
+            response_type: "headers".to_string(),
+            headers: vec![],
+            body: None,
+        }).await?;
+        let meta = ObjectMeta::from_headers(&XKey::parse(key)?.object_key(), &headers);
+
+        let part = format!("{}.part", dest);
+        let state_key = state_key(dest);
+        let part_size = std::fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+        let same_object = meta.etag.is_some()
+            && native.get_storage(&state_key).and_then(|state| state["etag"].as_str().map(|s| s.to_string())) == meta.etag;
+        let offset = if same_object && part_size < meta.size {
+            part_size
+        } else {
+            let _ = std::fs::remove_file(&part);
+            0
+        };
+        native.set_storage(&state_key, serde_json::json!({ "etag": meta.etag, "size": meta.size }));
+
+        let range = if offset > 0 {
+            vec![("Range".to_string(), format!("bytes={}-", offset))]
+        } else {
+            vec![]
+        };
+        native.download_file(DownloadArgs {
+            url: url.to_string(),
+            file_path: part.clone(),
+            headers: range,
+            offset,
+            on_progress: opts.on_progress.clone(),
+        }).await?;
+
+        if opts.verify {
+            if let Err(err) = check(&part, &meta) {
+                let _ = std::fs::remove_file(&part);
+                native.del_storage(&state_key);
+                return Err(err);
+            }
+        }
+        std::fs::rename(&part, dest)
+            .map_err(|e| XError::RequestFailed(format!("rename {}: {}", part, e)))?;
+        native.del_storage(&state_key);
+        Ok(meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use md5::{Digest, Md5};
+    use serde_json::Value;
+    use std::collections::{HashMap, HashSet};
+    use std::io::Write;
+    use std::sync::Mutex;
+    use crate::testing::ScriptedNative;
+    use crate::{ClouderOptions, Native};
+
+    #[derive(Default)]
+    struct State {
+        objects: HashMap<String, Vec<u8>>,
+        down_domains: HashSet<String>,
+        attempts: Vec<String>,
+        corrupt: bool,
+    }
+
+    // 模拟各域名的 HTTP 服务：HEAD 返回长度和 ETag，GET 支持 Range 续传
+    #[derive(Clone, Default)]
+    struct HttpServer {
+        state: Arc<Mutex<State>>,
+    }
+
+    impl HttpServer {
+        fn object(&self, url: &str) -> XResult<Vec<u8>> {
+            let mut state = self.state.lock().unwrap();
+            state.attempts.push(url.to_string());
+            let path = url.trim_start_matches("https://");
+            let domain = path.split('/').next().unwrap();
+            if state.down_domains.contains(domain) {
+                return Err(XError::NetworkError(format!("{} unreachable", domain)));
+            }
+            state.objects.get(path).cloned().ok_or_else(|| XError::NotFound(url.to_string()))
+        }
+
+        fn get(&self, args: &DownloadArgs) -> XResult<()> {
+            let mut data = self.object(&args.url)?;
+            if self.state.lock().unwrap().corrupt {
+                data[0] ^= 1;
+            }
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(args.offset > 0)
+                .write(true)
+                .truncate(args.offset == 0)
+                .open(&args.file_path)
+                .unwrap();
+            file.write_all(&data[args.offset as usize..]).unwrap();
+            // 和宿主一样上报整个文件的进度，续传从 offset 开始
+            if let Some(on_progress) = &args.on_progress {
+                on_progress(args.offset as f32 / data.len() as f32);
+                on_progress(1.0);
+            }
+            Ok(())
+        }
+
+        fn head(&self, args: &RequestArgs) -> XResult<Value> {
+            let data = self.object(&args.url)?;
+            Ok(serde_json::json!({
+                "Content-Length": data.len().to_string(),
+                "ETag": format!("\"{}\"", hex::encode(Md5::digest(&data))),
+            }))
+        }
+
+        fn native(&self) -> Arc<ScriptedNative> {
+            let native = ScriptedNative::new();
+            let server = self.clone();
+            native.on_download(move |args| server.get(args));
+            let server = self.clone();
+            native.on_request(move |args| server.head(args));
+            native
+        }
+    }
+
+    fn clouder(native: &Arc<ScriptedNative>) -> Clouder {
+        let clouder = Clouder::new(ClouderOptions::from_native(vec![], native.clone()));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_tos",
+                "cloud": "tos",
+                "buckets": [{ "name": "disk", "domain": "disk.tos.example.com", "cdnDomain": "cdn.example.com", "fallback": "_cos" }]
+            }, {
+                "name": "_cos",
+                "cloud": "cos",
+                "buckets": [{ "name": "disk", "domain": "disk.cos.example.com" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        clouder
+    }
+
+    fn dest(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("xclouder-download-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_download_fallback() {
+        let server = HttpServer::default();
+        {
+            let mut state = server.state.lock().unwrap();
+            state.objects.insert("disk.cos.example.com/_tos/a.txt".to_string(), b"hello world".to_vec());
+            state.down_domains.insert("cdn.example.com".to_string());
+        }
+        let native = server.native();
+        let clouder = clouder(&native);
+        let dest = dest("fallback");
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let recorder = progress.clone();
+
+        let meta = clouder.download("disk", "_tos/a.txt", &dest, DownloadOptions {
+            on_progress: Some(Arc::new(move |p| recorder.lock().unwrap().push(p))),
+            verify: true,
+        }).await.unwrap();
+        assert_eq!(meta.size, 11);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"hello world");
+        assert_eq!(*progress.lock().unwrap(), vec![0.0, 1.0]);
+        assert!(!std::path::Path::new(&format!("{}.part", dest)).exists());
+
+        // cdn 不可达，源站没有对象，最后从 fallback 的 cos 下载
+        assert_eq!(server.state.lock().unwrap().attempts, vec![
+            "https://cdn.example.com/_tos/a.txt",
+            "https://disk.tos.example.com/_tos/a.txt",
+            "https://disk.cos.example.com/_tos/a.txt",
+            "https://disk.cos.example.com/_tos/a.txt",
+        ]);
+        assert!(native.storage_keys().is_empty());
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_resume() {
+        let server = HttpServer::default();
+        let data = b"0123456789".to_vec();
+        let dest = dest("resume");
+        std::fs::write(format!("{}.part", dest), &data[..4]).unwrap();
+        server.state.lock().unwrap().objects.insert("cdn.example.com/_tos/b.txt".to_string(), data.clone());
+        let native = server.native();
+        native.set_storage(&state_key(&dest), serde_json::json!({ "etag": hex::encode(Md5::digest(&data)) }));
+        let clouder = clouder(&native);
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let recorder = progress.clone();
+
+        clouder.download("disk", "_tos/b.txt", &dest, DownloadOptions {
+            on_progress: Some(Arc::new(move |p| recorder.lock().unwrap().push(p))),
+            verify: true,
+        }).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        let downloads = native.downloads();
+        assert_eq!(downloads[0].offset, 4);
+        assert_eq!(downloads[0].headers, vec![("Range".to_string(), "bytes=4-".to_string())]);
+        assert_eq!(*progress.lock().unwrap(), vec![0.4, 1.0]);
+        std::fs::remove_file(&dest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_checksum_mismatch() {
+        let server = HttpServer::default();
+        {
+            let mut state = server.state.lock().unwrap();
+            state.objects.insert("cdn.example.com/_tos/c.txt".to_string(), b"abc".to_vec());
+            state.objects.insert("disk.tos.example.com/_tos/c.txt".to_string(), b"abc".to_vec());
+            state.objects.insert("disk.cos.example.com/_tos/c.txt".to_string(), b"abc".to_vec());
+            state.corrupt = true;
+        }
+        let native = server.native();
+        let clouder = clouder(&native);
+        let dest = dest("mismatch");
+
+        let err = clouder.download("disk", "_tos/c.txt", &dest, DownloadOptions {
+            on_progress: None,
+            verify: true,
+        }).await.unwrap_err();
+        assert!(matches!(err, XError::ChecksumMismatch(_)));
+        assert!(!std::path::Path::new(&dest).exists());
+        assert!(!std::path::Path::new(&format!("{}.part", dest)).exists());
+        assert_eq!(native.downloads().len(), 3);
+    }
+}
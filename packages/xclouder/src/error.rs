@@ -24,6 +24,65 @@ pub enum XError {
     
     #[error("Lock error: {0}")]
     LockError(String),
+
+    #[error("Sign failed: {0}")]
+    SignFailed(String),
+
+    #[error("Magic error: {0}")]
+    MagicError(String),
+
+    #[error("Operation {op} is not supported by {cloud}")]
+    UnsupportedOperation { cloud: String, op: String },
+
+    #[error("No available domain for {0}")]
+    NoAvailableDomain(String),
+
+    #[error("Invalid url: {0}")]
+    InvalidUrl(String),
+
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid key template: {0}")]
+    InvalidKeyTemplate(String),
+
+    // Native::request 遇到 404 时应返回该错误
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Native is not loaded")]
+    NativeNotLoaded,
+
+    #[error("Request failed: {0}")]
+    RequestFailed(String),
+
+    #[error("Checksum mismatch: {0}")]
+    ChecksumMismatch(String),
+}
+
+impl XError {
+    pub fn class(&self) -> &'static str {
+        match self {
+            XError::CloudNotFound => "cloud_not_found",
+            XError::BucketNotFound(_) => "bucket_not_found",
+            XError::UploadFailed(_) => "upload_failed",
+            XError::NetworkError(_) => "network",
+            XError::InvalidConfig => "invalid_config",
+            XError::SerdeError(_) => "serde",
+            XError::LockError(_) => "lock",
+            XError::SignFailed(_) => "sign",
+            XError::MagicError(_) => "magic",
+            XError::UnsupportedOperation { .. } => "unsupported",
+            XError::NoAvailableDomain(_) => "no_available_domain",
+            XError::InvalidUrl(_) => "invalid_url",
+            XError::InvalidKey(_) => "invalid_key",
+            XError::InvalidKeyTemplate(_) => "invalid_key_template",
+            XError::NotFound(_) => "not_found",
+            XError::NativeNotLoaded => "native_not_loaded",
+            XError::RequestFailed(_) => "request_failed",
+            XError::ChecksumMismatch(_) => "checksum_mismatch",
+        }
+    }
 }
 
 impl From<serde_json::Error> for XError {
@@ -0,0 +1,265 @@
+// 宿主能力按用途拆分为传输、键值存储、网络探测和兜底地址，在 ClouderOptions 中组合。
+// 服务端只需实现 Transport，其余可用默认实现；已实现 Native 的宿主自动获得全部能力
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::error::{XError, XResult};
+use crate::{DownloadArgs, Native, NetworkInfo, RequestArgs, UploadArgs, UploadPartArgs};
+
+// 响应约定同 Native::request，包括不带 Location 的 308 按成功返回
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn upload_file(&self, args: UploadArgs) -> XResult<()>;
+    async fn download_file(&self, _args: DownloadArgs) -> XResult<()> {
+        Err(XError::UnsupportedOperation {
+            cloud: "native".to_string(),
+            op: "download_file".to_string(),
+        })
+    }
+    async fn upload_part(&self, _args: UploadPartArgs) -> XResult<Value> {
+        Err(XError::UnsupportedOperation {
+            cloud: "native".to_string(),
+            op: "upload_part".to_string(),
+        })
+    }
+    async fn request(&self, args: RequestArgs) -> XResult<Value>;
+}
+
+pub trait KvStore: Send + Sync {
+    fn set_storage(&self, key: &str, value: Value);
+    fn get_storage(&self, key: &str) -> Option<Value>;
+    fn del_storage(&self, key: &str);
+}
+
+// 默认认为网络始终可用，上传失败时只按次数重试和切换域名
+#[async_trait]
+pub trait NetworkProbe: Send + Sync {
+    async fn check_network(&self) -> XResult<NetworkInfo> {
+        Ok(NetworkInfo {
+            has_system_proxy: false,
+            signal_strength: 100,
+            network_type: "unknown".to_string(),
+            dns_error: false,
+            check_error: None,
+        })
+    }
+    async fn check_dns(&self, _domain: &str) -> XResult<bool> {
+        Ok(true)
+    }
+}
+
+pub trait UrlFallback: Send + Sync {
+    fn resolve_fallback(&self, bucket: &str, key: &str) -> String;
+}
+
+// 进程内存储，STS 和续传进度不跨进程保留
+#[derive(Default)]
+pub struct MemoryKvStore {
+    storage: Mutex<HashMap<String, Value>>,
+}
+
+impl KvStore for MemoryKvStore {
+    fn set_storage(&self, key: &str, value: Value) {
+        if let Ok(mut storage) = self.storage.lock() {
+            storage.insert(key.to_string(), value);
+        }
+    }
+
+    fn get_storage(&self, key: &str) -> Option<Value> {
+        self.storage.lock().ok()?.get(key).cloned()
+    }
+
+    fn del_storage(&self, key: &str) {
+        if let Ok(mut storage) = self.storage.lock() {
+            storage.remove(key);
+        }
+    }
+}
+
+pub struct AssumeOnline;
+
+impl NetworkProbe for AssumeOnline {}
+
+#[async_trait]
+impl<T: Native + ?Sized> Transport for T {
+    async fn upload_file(&self, args: UploadArgs) -> XResult<()> {
+        Native::upload_file(self, args).await
+    }
+
+    async fn download_file(&self, args: DownloadArgs) -> XResult<()> {
+        Native::download_file(self, args).await
+    }
+
+    async fn upload_part(&self, args: UploadPartArgs) -> XResult<Value> {
+        Native::upload_part(self, args).await
+    }
+
+    async fn request(&self, args: RequestArgs) -> XResult<Value> {
+        Native::request(self, args).await
+    }
+}
+
+impl<T: Native + ?Sized> KvStore for T {
+    fn set_storage(&self, key: &str, value: Value) {
+        Native::set_storage(self, key, value)
+    }
+
+    fn get_storage(&self, key: &str) -> Option<Value> {
+        Native::get_storage(self, key)
+    }
+
+    fn del_storage(&self, key: &str) {
+        Native::del_storage(self, key)
+    }
+}
+
+#[async_trait]
+impl<T: Native + ?Sized> NetworkProbe for T {
+    async fn check_network(&self) -> XResult<NetworkInfo> {
+        Native::check_network(self).await
+    }
+
+    async fn check_dns(&self, domain: &str) -> XResult<bool> {
+        Native::check_dns(self, domain).await
+    }
+}
+
+impl<T: Native + ?Sized> UrlFallback for T {
+    fn resolve_fallback(&self, bucket: &str, key: &str) -> String {
+        Native::resolve_fallback(self, bucket, key)
+    }
+}
+
+// 组合后的宿主，客户端和策略内部仍然通过 Native 调用
+pub(crate) struct Host {
+    pub transport: Arc<dyn Transport>,
+    pub kv_store: Arc<dyn KvStore>,
+    pub network_probe: Arc<dyn NetworkProbe>,
+    pub url_fallback: Option<Arc<dyn UrlFallback>>,
+}
+
+#[async_trait]
+impl Native for Host {
+    async fn upload_file(&self, args: UploadArgs) -> XResult<()> {
+        self.transport.upload_file(args).await
+    }
+
+    async fn download_file(&self, args: DownloadArgs) -> XResult<()> {
+        self.transport.download_file(args).await
+    }
+
+    async fn upload_part(&self, args: UploadPartArgs) -> XResult<Value> {
+        self.transport.upload_part(args).await
+    }
+
+    async fn request(&self, args: RequestArgs) -> XResult<Value> {
+        self.transport.request(args).await
+    }
+
+    fn set_storage(&self, key: &str, value: Value) {
+        self.kv_store.set_storage(key, value)
+    }
+
+    fn get_storage(&self, key: &str) -> Option<Value> {
+        self.kv_store.get_storage(key)
+    }
+
+    fn del_storage(&self, key: &str) {
+        self.kv_store.del_storage(key)
+    }
+
+    // 没有兜底地址时调用方应直接返回 NoAvailableDomain，见 CloudClient::fallback_url
+    fn resolve_fallback(&self, bucket: &str, key: &str) -> String {
+        self.url_fallback.as_ref()
+            .map(|fallback| fallback.resolve_fallback(bucket, key))
+            .unwrap_or_default()
+    }
+
+    async fn check_network(&self) -> XResult<NetworkInfo> {
+        self.network_probe.check_network().await
+    }
+
+    async fn check_dns(&self, domain: &str) -> XResult<bool> {
+        self.network_probe.check_dns(domain).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::testing::{MemoryStore, MemoryStrategy};
+    use crate::{Clouder, ClouderOptions, UploadOptions};
+
+    // 服务端宿主只实现传输
+    #[derive(Default)]
+    struct ServerTransport {
+        uploads: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Transport for ServerTransport {
+        async fn upload_file(&self, args: UploadArgs) -> XResult<()> {
+            self.uploads.lock().unwrap().push(args.url);
+            Ok(())
+        }
+
+        async fn request(&self, _args: RequestArgs) -> XResult<Value> {
+            Ok(serde_json::json!({ "token": "sts", "expireAt": chrono::Utc::now().timestamp() + 3600 }))
+        }
+    }
+
+    struct Cdn;
+
+    impl UrlFallback for Cdn {
+        fn resolve_fallback(&self, bucket: &str, key: &str) -> String {
+            format!("https://fallback.example.com/{}/{}", bucket, key)
+        }
+    }
+
+    fn clouder(transport: Arc<ServerTransport>, url_fallback: Option<Arc<dyn UrlFallback>>) -> Clouder {
+        let clouder = Clouder::new(ClouderOptions {
+            url_fallback,
+            ..ClouderOptions::new(vec![Box::new(MemoryStrategy::new("mem", MemoryStore::new()))], transport)
+        });
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_mem",
+                "cloud": "mem",
+                "buckets": [{ "name": "img", "domain": "a.mem.test" }, { "name": "doc" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        clouder
+    }
+
+    #[tokio::test]
+    async fn test_transport_only_host() {
+        let transport = Arc::new(ServerTransport::default());
+        let clouder = clouder(transport.clone(), None);
+        let path = std::env::temp_dir().join(format!("xclouder-host-{}.jpg", std::process::id()));
+        std::fs::File::create(&path).unwrap().write_all(b"hello").unwrap();
+
+        let url = clouder.upload("img", &path.to_string_lossy(), None, UploadOptions {
+            cloud_name: Some("_mem".to_string()),
+            on_progress: None,
+            disable_retry: false,
+            manual_retry: false,
+            openid: None,
+            dedup: false,
+        }).await.unwrap();
+        assert!(url.starts_with("https://a.mem.test/_mem/"));
+        assert_eq!(*transport.uploads.lock().unwrap(), vec!["https://a.mem.test"]);
+
+        // 未配置兜底地址时保留原错误
+        assert!(matches!(clouder.resolve("doc", "_mem/a.pdf", &[]), Err(XError::NoAvailableDomain(_))));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_url_fallback() {
+        let clouder = clouder(Arc::new(ServerTransport::default()), Some(Arc::new(Cdn)));
+        assert_eq!(clouder.resolve("doc", "_mem/a.pdf", &[]).unwrap(), "https://fallback.example.com/doc/_mem/a.pdf");
+    }
+}
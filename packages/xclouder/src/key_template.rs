@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use crate::error::{XError, XResult};
+use crate::resolver::magic::placeholders;
+use crate::utils;
+
+// 与 fill_name 生成的 {cloud_name}/{openid}/{shot_unique}{ext} 保持一致
+pub const DEFAULT_KEY_TEMPLATE: &str = "{cloud}/{openid}/{uid}{ext}";
+
+// 秒传模式下 key 只由内容决定
+pub const DEDUP_KEY_TEMPLATE: &str = "{cloud}/{hash}{ext}";
+
+const PLACEHOLDERS: [&str; 11] = ["cloud", "bucket", "yyyy", "mm", "dd", "openid", "uid", "ext", "hash", "name", "rand"];
+
+// 至少包含一个唯一性占位符，避免不同文件生成相同的 key
+const UNIQUE_PLACEHOLDERS: [&str; 3] = ["uid", "hash", "rand"];
+
+pub struct KeyContext<'a> {
+    pub cloud: &'a str,
+    pub bucket: &'a str,
+    pub file_path: &'a str,
+    pub openid: Option<&'a str>,
+    pub now: DateTime<Utc>,
+    // 已经算好的内容哈希，避免重复读取文件
+    pub content_hash: Option<&'a str>,
+}
+
+pub fn validate(template: &str) -> XResult<()> {
+    let names = placeholders(template)
+        .map_err(|e| XError::InvalidKeyTemplate(format!("{}: {}", template, e)))?;
+    if let Some(name) = names.iter().find(|name| !PLACEHOLDERS.contains(name)) {
+        return Err(XError::InvalidKeyTemplate(format!("{}: unknown placeholder {{{}}}", template, name)));
+    }
+    if !template.starts_with("{cloud}/") {
+        return Err(XError::InvalidKeyTemplate(format!("{}: must start with {{cloud}}/", template)));
+    }
+    if !names.iter().any(|name| UNIQUE_PLACEHOLDERS.contains(name)) {
+        return Err(XError::InvalidKeyTemplate(format!("{}: requires one of {{uid}}, {{hash}} or {{rand}}", template)));
+    }
+    Ok(())
+}
+
+pub fn render(template: &str, ctx: &KeyContext) -> XResult<String> {
+    validate(template)?;
+    let mut key = template.to_string();
+    for name in placeholders(template)? {
+        let value = match name {
+            "cloud" => ctx.cloud.to_string(),
+            "bucket" => ctx.bucket.to_string(),
+            "yyyy" => ctx.now.format("%Y").to_string(),
+            "mm" => ctx.now.format("%m").to_string(),
+            "dd" => ctx.now.format("%d").to_string(),
+            "openid" => ctx.openid.unwrap_or("anonymous").to_string(),
+            "uid" => utils::shot_unique(),
+            "ext" => utils::extract_ext(ctx.file_path),
+            "hash" => match ctx.content_hash {
+                Some(hash) => hash.to_string(),
+                None => content_hash(ctx.file_path)?,
+            },
+            "name" => slug(ctx.file_path),
+            "rand" => random_id(),
+            _ => unreachable!(),
+        };
+        key = key.replacen(&format!("{{{}}}", name), &value, 1);
+    }
+    Ok(key)
+}
+
+pub fn content_hash(file_path: &str) -> XResult<String> {
+    let mut file = std::fs::File::open(file_path)
+        .map_err(|e| XError::UploadFailed(format!("read {}: {}", file_path, e)))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| XError::UploadFailed(format!("read {}: {}", file_path, e)))?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// 原文件名转成只包含小写字母、数字和 - 的片段
+fn slug(file_path: &str) -> String {
+    let name = file_path.rsplit(['/', '\\']).next().unwrap_or(file_path);
+    let stem = name.rsplit_once('.').map(|(stem, _)| stem).filter(|s| !s.is_empty()).unwrap_or(name);
+    let slug = stem.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() { "file".to_string() } else { slug }
+}
+
+fn random_id() -> String {
+    use rand::Rng;
+    const CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut rng = rand::thread_rng();
+    (0..8).map(|_| CHARS[rng.gen_range(0..CHARS.len())] as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(DEFAULT_KEY_TEMPLATE).is_ok());
+        assert!(validate("{cloud}/{bucket}/{yyyy}/{mm}/{openid}/{uid}{ext}").is_ok());
+        assert!(validate("{cloud}/{foo}/{uid}").is_err());
+        assert!(validate("{bucket}/{uid}").is_err());
+        assert!(validate("{cloud}/{openid}{ext}").is_err());
+        assert!(validate("{cloud}/{uid").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let ctx = KeyContext {
+            cloud: "_cos",
+            bucket: "img",
+            file_path: "/tmp/My Photo (1).JPG",
+            openid: Some("user"),
+            now: Utc.with_ymd_and_hms(2024, 12, 1, 8, 0, 0).unwrap(),
+            content_hash: None,
+        };
+        let key = render("{cloud}/{bucket}/{yyyy}/{mm}/{dd}/{openid}/{name}-{rand}{ext}", &ctx).unwrap();
+        assert!(key.starts_with("_cos/img/2024/12/01/user/my-photo-1-"));
+        assert!(key.ends_with(".JPG"));
+        assert!(render("{cloud}/{hash}{ext}", &ctx).is_err());
+    }
+}
@@ -1,81 +1,325 @@
 mod cloud_client;
 mod error;
-mod strategy;
+pub mod strategy;
+pub mod host;
 pub mod resolver;
 mod events;
 mod network;
-use config::{BucketSource, CloudMagic};
+pub mod metrics;
 pub use network::NetworkInfo;
 mod utils;
 mod config;
 mod inner;
+mod key_template;
+pub mod unique_id;
+pub mod xkey;
+pub mod migrate;
+pub mod download;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "native-reqwest")]
+pub mod native_reqwest;
 
-use cloud_client::{CloudClient, UploadOpts};
-use error::XResult;
-use strategy::{Strategy, UrlRes};
+use cloud_client::{CloudClient, ConfigSnapshot};
+use strategy::Strategy;
+pub use strategy::ObjectMeta;
 use serde_json::Value;
 use std::{collections::HashMap, sync::Arc};
 
-pub struct Clouder<'a> {
-    client: CloudClient<'a>,
+// 克隆只增加引用计数，可以放进 Arc 或直接传给 tokio::spawn
+#[derive(Clone)]
+pub struct Clouder {
+    client: Arc<CloudClient>,
 }
 
-impl<'a> Clouder<'a> {
+impl Clouder {
     pub fn new(opts: ClouderOptions) -> Self {
-        let mut client = CloudClient::new(opts.native);
+        let native: Arc<dyn Native> = Arc::new(host::Host {
+            transport: opts.transport,
+            kv_store: opts.kv_store,
+            network_probe: opts.network_probe,
+            url_fallback: opts.url_fallback.clone(),
+        });
+        let mut client = CloudClient::new(native, opts.url_fallback, metrics::Metrics::new(opts.metrics));
         
         for strategy in opts.strategy {
             client.load_strategy(strategy);
         }
         
-        Self { client }
+        Self { client: Arc::new(client) }
     }
 
-    pub fn xcm(&self) -> &HashMap<String, CloudMagic> {
-        &self.client.cloud_magics_map
+    pub fn xcm(&self) -> XResult<HashMap<String, CloudMagic>> {
+        Ok(self.client.snapshot()?.cloud_magics_map.clone())
     }
 
-    pub fn xc(&self) -> &HashMap<String, HashMap<String, BucketSource>> {
-        &self.client.branch_cloud_source
+    pub fn xc(&self) -> XResult<HashMap<String, HashMap<String, BucketSource>>> {
+        Ok(self.client.snapshot()?.branch_cloud_source.iter()
+            .map(|(bucket, sources)| {
+                let sources = sources.iter().map(|(cloud, source)| (cloud.clone(), source.as_ref().clone())).collect();
+                (bucket.clone(), sources)
+            })
+            .collect())
     }
 
-    pub fn init(&mut self, remote: Option<String>, config: serde_json::Value) -> &mut Self {
-        self.client.init(remote, config);
-        self
+    // 重新加载配置，已经开始的请求继续使用旧的配置快照
+    pub fn init(&self, remote: Option<String>, config: serde_json::Value) -> XResult<&Self> {
+        self.client.init(remote, config)?;
+        Ok(self)
     }
 
+    // filename 为空时按 bucket 的 keyTemplate 生成 key
     pub async fn upload(
-        &'a self,
+        &self,
         bucket: &str,
         file_path: &str,
-        filename: String,
+        filename: Option<String>,
         opts: UploadOptions,
     ) -> XResult<String> {
         println!("[XClouder] upload {} {}", bucket, file_path);
         let default_cloud = "_main".to_string();
         let cloud_name = opts.cloud_name.as_ref().unwrap_or(&default_cloud);
-        let bucket_source = self.client.current_bucket_source(bucket, cloud_name, true)?;
+        let snapshot = self.client.snapshot()?;
+        let bucket_source = snapshot.current_bucket_source(bucket, cloud_name, true)?;
         let up_id = chrono::Utc::now().timestamp_millis();
-        
-        let key = format!("{}/{}", cloud_name, filename);
-        
-        self.client.upload_fn(UploadOpts {
-            bucket_source,
+
+        if let (true, Some(filename)) = (opts.dedup, &filename) {
+            return Err(error::XError::InvalidKey(format!("{} cannot be used with dedup", filename)));
+        }
+        let content_hash = if opts.dedup {
+            Some(key_template::content_hash(file_path)?)
+        } else {
+            None
+        };
+        let key = Self::build_key(bucket, bucket_source, cloud_name, file_path, filename, opts.openid.as_deref(), content_hash.as_deref())?;
+        let filename = XKey::parse(&key)?.path().to_string();
+
+        if let Some(hash) = &content_hash {
+            if let Some(url) = self.client.find_existing(bucket_source, &key, hash).await? {
+                return Ok(url);
+            }
+        }
+
+        let url = self.client.upload_fn(UploadOpts {
+            bucket_source: bucket_source.clone(),
             bucket: bucket.to_string(),
             filename,
             file_path: file_path.to_string(),
-            key: key,
+            key,
             on_progress: opts.on_progress,
             up_id,
             disable_retry: opts.disable_retry,
             manual_retry: opts.manual_retry,
-        }).await
+        }).await?;
+
+        if let Some(hash) = &content_hash {
+            self.client.native.set_storage(&CloudClient::dedup_storage_key(bucket_source, hash), Value::String(url.clone()));
+        }
+        Ok(url)
+    }
+
+    fn build_key(
+        bucket: &str,
+        bucket_source: &BucketSource,
+        cloud_name: &str,
+        file_path: &str,
+        filename: Option<String>,
+        openid: Option<&str>,
+        content_hash: Option<&str>,
+    ) -> XResult<String> {
+        let template = match content_hash {
+            Some(_) => key_template::DEDUP_KEY_TEMPLATE,
+            None => bucket_source.key_template.as_deref().unwrap_or(key_template::DEFAULT_KEY_TEMPLATE),
+        };
+        match filename {
+            Some(filename) if content_hash.is_none() => Ok(format!("{}/{}", cloud_name, filename)),
+            _ => key_template::render(template, &key_template::KeyContext {
+                cloud: cloud_name,
+                bucket,
+                file_path,
+                openid,
+                now: chrono::Utc::now(),
+                content_hash,
+            }),
+        }
+    }
+
+    // 按上传同样的规则选桶和生成 key，返回给第三方客户端直传的表单。
+    // 只支持 cos、oss、tos，其他云返回 UnsupportedOperation
+    pub async fn presign_upload(&self, bucket: &str, opts: PresignUploadOptions) -> XResult<PresignedUpload> {
+        let default_cloud = "_main".to_string();
+        let cloud_name = opts.cloud_name.as_ref().unwrap_or(&default_cloud);
+        let snapshot = self.client.snapshot()?;
+        let bucket_source = snapshot.current_bucket_source(bucket, cloud_name, true)?;
+        let cloud = bucket_source.cloud.as_deref().unwrap_or("");
+        if !resolver::sign::POST_POLICY_CLOUDS.contains(&cloud) {
+            return Err(error::XError::UnsupportedOperation {
+                cloud: cloud.to_string(),
+                op: "presign_upload".to_string(),
+            });
+        }
+        let key = Self::build_key(
+            bucket,
+            bucket_source,
+            cloud_name,
+            opts.file_name.as_deref().unwrap_or(""),
+            None,
+            opts.openid.as_deref(),
+            None,
+        )?;
+
+        let strategy = self.client.bucket_strategy(bucket_source)?;
+        let sts = strategy.get_sts(bucket_source).await?;
+        let credentials = strategy::Credentials::from_sts(&sts)
+            .ok_or_else(|| error::XError::SignFailed("missing credentials in sts".to_string()))?;
+        let domain = bucket_source.domain.as_deref().ok_or(error::XError::InvalidConfig)?;
+        let location = strategy.domain_parser(domain);
+
+        // 不超过临时密钥的有效期
+        let now = chrono::Utc::now().timestamp();
+        let mut expires = opts.expires;
+        if let Some(expire_at) = sts["expireAt"].as_i64().or_else(|| sts["expiredTime"].as_i64()) {
+            expires = expires.min(expire_at - now);
+        }
+        if expires <= 0 {
+            return Err(error::XError::SignFailed("sts credentials expired".to_string()));
+        }
+
+        let fields = resolver::sign::post_policy(strategy.name(), &resolver::sign::PostPolicy {
+            bucket: location["bucket"].as_str().unwrap_or_else(|| domain.split('.').next().unwrap_or("")),
+            region: location["region"].as_str().unwrap_or(""),
+            key: &key,
+            content_type: opts.content_type.as_deref(),
+            now,
+            expires,
+        }, &credentials)?;
+
+        Ok(PresignedUpload {
+            url: bucket_source.base_url(domain),
+            method: "POST".to_string(),
+            fields,
+            headers: vec![],
+            file_field: "file".to_string(),
+            key,
+            expires_at: now + expires,
+        })
     }
 
     pub fn resolve(&self, bucket: &str, key: &str, magics: &[&str]) -> XResult<String> {
-        let branch_cloud_source = self.client.current_branch_cloud_source(bucket)?;
-        let magics = self.client.current_magics(magics)?;
-        Ok(crate::resolver::resolve(branch_cloud_source, key, &magics))
+        let snapshot = self.client.snapshot()?;
+        let magics = snapshot.current_magics(magics)?;
+        self.resolve_magics(&snapshot, bucket, key, &magics, None)
+    }
+
+    // 限定了 clouds 时不使用宿主的兜底地址，避免返回未经处理的原图
+    fn resolve_magics(&self, snapshot: &ConfigSnapshot, bucket: &str, key: &str, magics: &[CloudMagic], clouds: Option<&[&str]>) -> XResult<String> {
+        let branch_cloud_source = snapshot.current_branch_cloud_source(bucket)?;
+        let unhealthy_domains = self.client.unhealthy_domains.lock()?;
+        let opts = resolver::ResolveOptions {
+            config: snapshot.config.as_ref(),
+            unhealthy_domains: Some(&unhealthy_domains),
+            clouds,
+        };
+        match crate::resolver::resolve(branch_cloud_source, key, magics, &opts) {
+            Err(error::XError::NoAvailableDomain(domain)) if clouds.is_none() => self.client.fallback_url(bucket, key)
+                .ok_or(error::XError::NoAvailableDomain(domain)),
+            res => res,
+        }
+    }
+
+    pub fn parse_url(&self, url: &str) -> XResult<resolver::parse::ParsedUrl> {
+        let snapshot = self.client.snapshot()?;
+        let config = snapshot.config.as_ref().ok_or(error::XError::InvalidConfig)?;
+        resolver::parse::parse_url(config, &snapshot.cloud_magics_map, url)
+    }
+
+    pub fn mark_domain_unhealthy(&self, domain: &str) -> XResult<()> {
+        self.client.unhealthy_domains.lock()?.insert(domain.to_string());
+        Ok(())
+    }
+
+    pub fn mark_domain_healthy(&self, domain: &str) -> XResult<()> {
+        self.client.unhealthy_domains.lock()?.remove(domain);
+        Ok(())
+    }
+
+    pub fn resolve_process(&self, bucket: &str, key: &str, magics: &[&str], process: &resolver::process::Process) -> XResult<String> {
+        let snapshot = self.client.snapshot()?;
+        let mut magics = snapshot.current_magics(magics)?;
+        let (bucket_source, _) = snapshot.key_bucket_source(bucket, key)?;
+        let cloud = bucket_source.cloud.as_deref().ok_or(error::XError::CloudNotFound)?;
+        process.render(cloud)?;
+        let clouds = process.supported_clouds();
+        magics.push(process.to_magic());
+        self.resolve_magics(&snapshot, bucket, key, &magics, Some(&clouds))
+    }
+
+    pub async fn resolve_signed(&self, bucket: &str, key: &str, magics: &[&str], expires: i64) -> XResult<String> {
+        let snapshot = self.client.snapshot()?;
+        let magics = snapshot.current_magics(magics)?;
+        let (bucket_source, _) = snapshot.key_bucket_source(bucket, key)?;
+        let strategy = self.client.bucket_strategy(bucket_source)?;
+
+        let sts = strategy.get_sts(bucket_source).await?;
+        let credentials = strategy::Credentials::from_sts(&sts)
+            .ok_or_else(|| error::XError::SignFailed("missing credentials in sts".to_string()))?;
+        let location = strategy.domain_parser(bucket_source.domain.as_deref().unwrap_or(""));
+
+        crate::resolver::resolve_signed(bucket_source, key, &magics, &credentials, &location, expires)
+    }
+
+    pub async fn head(&self, bucket: &str, key: &str) -> XResult<ObjectMeta> {
+        let snapshot = self.client.snapshot()?;
+        let (bucket_source, key) = snapshot.key_bucket_source(bucket, key)?;
+        self.client.bucket_strategy(bucket_source)?.head(bucket_source, &key).await
+    }
+
+    pub async fn delete(&self, bucket: &str, key: &str) -> XResult<()> {
+        let snapshot = self.client.snapshot()?;
+        let (bucket_source, key) = snapshot.key_bucket_source(bucket, key)?;
+        self.client.bucket_strategy(bucket_source)?.delete(bucket_source, &key).await?;
+        self.client.forget_dedup(bucket_source, &key);
+        Ok(())
+    }
+
+    // 按云源分组后批量删除
+    pub async fn delete_many(&self, bucket: &str, keys: &[&str]) -> XResult<()> {
+        let snapshot = self.client.snapshot()?;
+        let mut groups: Vec<(&BucketSource, Vec<String>)> = Vec::new();
+        for key in keys {
+            let (bucket_source, key) = snapshot.key_bucket_source(bucket, key)?;
+            match groups.iter_mut().find(|(bs, _)| std::ptr::eq(*bs, bucket_source)) {
+                Some((_, keys)) => keys.push(key),
+                None => groups.push((bucket_source, vec![key])),
+            }
+        }
+        for (bucket_source, keys) in groups {
+            self.client.bucket_strategy(bucket_source)?.delete_many(bucket_source, &keys).await?;
+            keys.iter().for_each(|key| self.client.forget_dedup(bucket_source, key));
+        }
+        Ok(())
+    }
+
+    // 只支持同一家云内复制，跨云请用迁移
+    pub async fn copy(&self, bucket: &str, src_key: &str, dest_key: &str) -> XResult<()> {
+        let snapshot = self.client.snapshot()?;
+        let (source, src_key) = snapshot.key_bucket_source(bucket, src_key)?;
+        let (target, dest_key) = snapshot.key_bucket_source(bucket, dest_key)?;
+        if source.cloud != target.cloud {
+            return Err(error::XError::UnsupportedOperation {
+                cloud: target.cloud.clone().unwrap_or_default(),
+                op: format!("copy from {}", source.cloud.as_deref().unwrap_or("")),
+            });
+        }
+        self.client.bucket_strategy(target)?.copy(source, &src_key, target, &dest_key).await
+    }
+
+    pub async fn list(&self, bucket: &str, prefix: &str) -> XResult<Vec<ObjectMeta>> {
+        let snapshot = self.client.snapshot()?;
+        let cloud_name = xkey::prefix_cloud_name(prefix, snapshot.legacy_rules());
+        let bucket_source = snapshot.cloud_bucket_source(bucket, cloud_name)?;
+        let prefix = prefix.strip_prefix('/').unwrap_or(prefix);
+        self.client.bucket_strategy(bucket_source)?.list(bucket_source, prefix).await
     }
 
     pub fn is_xclouder(&self, key: &str) -> bool {
@@ -93,7 +337,38 @@ impl<'a> Clouder<'a> {
 
 pub struct ClouderOptions {
     pub strategy: Vec<Box<dyn Strategy>>,
-    pub native: Box<dyn Native>,
+    pub transport: Arc<dyn host::Transport>,
+    pub kv_store: Arc<dyn host::KvStore>,
+    pub network_probe: Arc<dyn host::NetworkProbe>,
+    // 未配置时没有可用域名直接返回 NoAvailableDomain
+    pub url_fallback: Option<Arc<dyn host::UrlFallback>>,
+    pub metrics: Vec<Arc<dyn metrics::MetricsSink>>,
+}
+
+impl ClouderOptions {
+    // 只提供传输能力，存储在进程内，网络视为始终可用
+    pub fn new(strategy: Vec<Box<dyn Strategy>>, transport: Arc<dyn host::Transport>) -> Self {
+        Self {
+            strategy,
+            transport,
+            kv_store: Arc::new(host::MemoryKvStore::default()),
+            network_probe: Arc::new(host::AssumeOnline),
+            url_fallback: None,
+            metrics: vec![],
+        }
+    }
+
+    // 已实现完整 Native 的宿主
+    pub fn from_native<N: Native + 'static>(strategy: Vec<Box<dyn Strategy>>, native: Arc<N>) -> Self {
+        Self {
+            strategy,
+            transport: native.clone(),
+            kv_store: native.clone(),
+            network_probe: native.clone(),
+            url_fallback: Some(native),
+            metrics: vec![],
+        }
+    }
 }
 
 pub struct UploadOptions {
@@ -102,6 +377,30 @@ pub struct UploadOptions {
     pub disable_retry: bool,
     pub manual_retry: bool,
     pub openid: Option<String>,
+    // 按内容哈希生成 {cloud}/{hash}{ext} 形式的 key，不使用 bucket 的 keyTemplate，
+    // 不能和 filename 同时指定；对象已存在时跳过上传
+    pub dedup: bool,
+}
+
+pub struct PresignUploadOptions {
+    pub cloud_name: Option<String>,
+    // 只用于生成 key 的扩展名和文件名片段
+    pub file_name: Option<String>,
+    pub openid: Option<String>,
+    pub content_type: Option<String>,
+    pub expires: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresignedUpload {
+    pub url: String,
+    pub method: String,
+    pub fields: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub file_field: String,
+    pub key: String,
+    pub expires_at: i64,
 }
 
 #[derive(Clone)]
@@ -132,6 +431,8 @@ pub struct RequestArgs {
     pub enable_cache: bool,
     pub timeout: u32,
     pub response_type: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
 }
 
 impl std::fmt::Debug for RequestArgs {
@@ -142,13 +443,68 @@ impl std::fmt::Debug for RequestArgs {
             .field("enable_cache", &self.enable_cache)
             .field("timeout", &self.timeout)
             .field("response_type", &self.response_type)
+            .field("headers", &self.headers)
+            .field("body", &self.body)
             .finish()
     }
 }
 
+#[derive(Clone)]
+pub struct DownloadArgs {
+    pub url: String,
+    pub file_path: String,
+    pub headers: Vec<(String, String)>,
+    // 大于 0 时 headers 带有 Range，响应为 206 则从该位置续写文件，否则从头覆盖
+    pub offset: u64,
+    // 上报整个文件的完成比例：(已有的 offset + 本次写入) / 对象大小，不是本次请求的比例
+    pub on_progress: Option<Arc<dyn Fn(f32) + Send + Sync>>,
+}
+
+impl std::fmt::Debug for DownloadArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DownloadArgs")
+            .field("url", &self.url)
+            .field("file_path", &self.file_path)
+            .field("headers", &self.headers)
+            .field("offset", &self.offset)
+            .field("on_progress", &self.on_progress.as_ref().map(|_| "Fn(f32)"))
+            .finish()
+    }
+}
+
+// 分片上传中的一个分片，请求体为文件 offset 处开始的 size 字节
+#[derive(Debug, Clone)]
+pub struct UploadPartArgs {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub file_path: String,
+    pub offset: u64,
+    pub size: u64,
+    pub response_type: String,
+}
+
+// 完整的宿主能力，实现了它即自动实现 host 中的各个能力 trait，可用 ClouderOptions::from_native 接入
 #[async_trait::async_trait]
 pub trait Native: Send + Sync {
     async fn upload_file(&self, args: UploadArgs) -> XResult<()>;
+    // 下载到本地文件，宿主未实现时不支持下载和跨云迁移
+    async fn download_file(&self, _args: DownloadArgs) -> XResult<()> {
+        Err(error::XError::UnsupportedOperation {
+            cloud: "native".to_string(),
+            op: "download_file".to_string(),
+        })
+    }
+    // 上传单个分片，宿主未实现时大文件只能走表单上传。
+    // 响应约定同 request，GCS 分片未传完时的 308 也要按成功返回
+    async fn upload_part(&self, _args: UploadPartArgs) -> XResult<serde_json::Value> {
+        Err(error::XError::UnsupportedOperation {
+            cloud: "native".to_string(),
+            op: "upload_part".to_string(),
+        })
+    }
+    // 404 返回 XError::NotFound，其余非 2xx 返回 XError::RequestFailed；
+    // 例外是不带 Location 的 308（GCS Resume Incomplete），response_type 为 headers/text 时按成功返回响应头/正文
     async fn request(&self, args: RequestArgs) -> XResult<serde_json::Value>;
     fn set_storage(&self, key: &str, value: serde_json::Value);
     fn get_storage(&self, key: &str) -> Option<serde_json::Value>;
@@ -158,141 +514,30 @@ pub trait Native: Send + Sync {
     async fn check_dns(&self, domain: &str) -> XResult<bool>;
 }
 
-pub use config::Config;
+// 实现 Strategy、FormProvider 需要的类型
+pub use cloud_client::UploadOpts;
+pub use config::{BucketSource, CdnAuth, CloudMagic, CloudSource, Config, MagicParam};
+pub use error::{XError, XResult};
+pub use xkey::XKey;
 
 #[cfg(test)]
 mod tests {
-    use async_trait::async_trait;
-    use config::BucketSource;
-
     use super::*;
     use std::sync::Arc;
-    use std::collections::HashMap;
-    use std::sync::Mutex;
+    use crate::testing::{upload_fixture, MemoryStore, MemoryStrategy, ScriptedNative};
 
-    struct MockNative {
-        storage: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+    fn mock_options(native: &Arc<ScriptedNative>, store: &Arc<MemoryStore>) -> ClouderOptions {
+        ClouderOptions::from_native(vec![Box::new(MemoryStrategy::new("mock", store.clone()))], native.clone())
     }
 
-    impl MockNative {
-        fn new() -> Self {
-            Self {
-                storage: Arc::new(Mutex::new(HashMap::new())),
-            }
-        }
-    }
-
-    #[async_trait::async_trait]
-    impl Native for MockNative {
-        async fn upload_file(&self, args: UploadArgs) -> XResult<()> {
-            println!("[Mock] upload_file: {:?}", args);
-            Ok(())
-        }
-
-        async fn request(&self, args: RequestArgs) -> XResult<serde_json::Value> {
-            println!("[Mock] request: {:?}", args);
-            Ok(serde_json::json!({
-                "expireAt": chrono::Utc::now().timestamp() + 3600,
-                "mergeFormData": {
-                    "token": "mock_token"
-                }
-            }))
-        }
-
-        fn set_storage(&self, key: &str, value: serde_json::Value) {
-            self.storage.lock().unwrap().insert(key.to_string(), value);
-        }
-
-        fn get_storage(&self, key: &str) -> Option<serde_json::Value> {
-            self.storage.lock().unwrap().get(key).cloned()
-        }
-
-        fn del_storage(&self, key: &str) {
-            self.storage.lock().unwrap().remove(key);
-        }
-
-        fn resolve_fallback(&self, bucket: &str, key: &str) -> String {
-            format!("https://mock.{}.com/{}", bucket, key)
-        }
-
-        async fn check_network(&self) -> XResult<NetworkInfo> {
-            Ok(NetworkInfo {
-                has_system_proxy: false,
-                signal_strength: 100,
-                network_type: "wifi".to_string(),
-                dns_error: false,
-                check_error: None,
-            })
-        }
-
-        async fn check_dns(&self, domain: &str) -> XResult<bool> {
-            Ok(true)
-        }
-    }
-
-    // 添加个 Mock 策略实现
-    struct MockStrategy {
-        name: String,
-        native: Option<Box<dyn Native>>,
-    }
-
-    impl MockStrategy {
-        fn new(name: &str) -> Self {
-            Self {
-                name: name.to_string(),
-                native: None,
-            }
-        }
-    }
-
-    #[async_trait]
-    impl Strategy for MockStrategy {
-        fn name(&self) -> &str {
-            &self.name
-        }
-
-        fn load_native(&mut self, native: Box<dyn Native>) {
-            self.native = Some(native);
-        }
-
-        fn storage_key(&self, bucket_source: &BucketSource) -> String {
-            format!("sts:mock:{}", bucket_source.name)
-        }
-
-        fn domain_parser(&self, domain: &str) -> Value {
-            serde_json::json!({
-                "mock": true,
-                "domain": domain
-            })
-        }
-
-        async fn get_sts(&self, bucket_source: &BucketSource, opts: &UploadOpts<'_>) -> XResult<Value> {
-            Ok(serde_json::json!({
-                "mergeFormData": {
-                    "token": "mock_sts_token"
-                }
-            }))
-        }
-
-        async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts<'_>) -> XResult<UrlRes> {
-            Ok(UrlRes {
-                base_url: format!("https://{}", bucket_source.domain.clone().unwrap()),
-                key: opts.key.clone(),
-                domain: bucket_source.domain.clone().unwrap(),
-                bucket: bucket_source.name.clone(),
-            })
-        }
+    // 上传用的本地文件，name 带扩展名以便自动命名时取到后缀
+    fn fixture(name: &str, data: &[u8]) -> String {
+        upload_fixture(name, data, serde_json::json!({ "name": "test", "cloud": "mock" }), "test.jpg").1.file_path
     }
 
     #[tokio::test]
     async fn test_upload() {
-        let native = Box::new(MockNative::new());
-        let opts = ClouderOptions {
-            strategy: vec![Box::new(MockStrategy::new("mock"))],
-            native,
-        };
-        
-        let mut clouder = Clouder::new(opts);
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &MemoryStore::new()));
         clouder.init(None, serde_json::json!({
             "cloudSource": [{
                 "name": "_mock",
@@ -306,18 +551,20 @@ mod tests {
                 }]
             }],
             "cloudMagics": []
-        }));
+        })).unwrap();
 
+        let path = fixture("upload.jpg", b"hello");
         let result = clouder.upload(
             "test",
-            "test.jpg",
-            "test.jpg".to_string(),
+            &path,
+            Some("test.jpg".to_string()),
             UploadOptions {
                 cloud_name: Some("_mock".to_string()),
                 on_progress: None,
                 disable_retry: false,
                 manual_retry: false,
                 openid: Some("test_user".to_string()),
+                dedup: false,
             }
         ).await;
 
@@ -326,15 +573,77 @@ mod tests {
         assert!(url.contains("test.mock.com"));
     }
 
+    #[tokio::test]
+    async fn test_builtin_strategy_receives_native() {
+        let native = ScriptedNative::new();
+        let clouder = Clouder::new(ClouderOptions::from_native(vec![Box::new(strategy::oss::Oss::new())], native.clone()));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_oss",
+                "cloud": "oss",
+                "buckets": [{ "name": "test", "domain": "test.oss-cn-hangzhou.aliyuncs.com" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+
+        let path = fixture("oss.jpg", b"hello");
+        let url = clouder.upload("test", &path, Some("test.jpg".to_string()), UploadOptions {
+            cloud_name: Some("_oss".to_string()),
+            on_progress: None,
+            disable_retry: true,
+            manual_retry: false,
+            openid: None,
+            dedup: false,
+        }).await.unwrap();
+        assert!(url.contains("test.oss-cn-hangzhou.aliyuncs.com"));
+        native.assert_attempted(&["test.oss-cn-hangzhou.aliyuncs.com"]);
+        assert_eq!(native.uploads()[0].url, "https://test.oss-cn-hangzhou.aliyuncs.com");
+    }
+
+    #[tokio::test]
+    async fn test_upload_from_spawned_tasks() {
+        fn assert_shareable<T: Send + Sync + Clone + 'static>() {}
+        assert_shareable::<Clouder>();
+
+        let store = MemoryStore::new();
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &store));
+        let path = fixture("spawned.jpg", b"hello");
+        let config = |domain: &str| serde_json::json!({
+            "cloudSource": [{
+                "name": "_mock",
+                "cloud": "mock",
+                "buckets": [{ "name": "test", "domain": domain }]
+            }],
+            "cloudMagics": []
+        });
+        clouder.init(None, config("a.mock.com")).unwrap();
+
+        let tasks: Vec<_> = (0..4).map(|i| {
+            let (clouder, path) = (clouder.clone(), path.clone());
+            tokio::spawn(async move {
+                clouder.upload("test", &path, Some(format!("{}.jpg", i)), UploadOptions {
+                    cloud_name: Some("_mock".to_string()),
+                    on_progress: None,
+                    disable_retry: true,
+                    manual_retry: false,
+                    openid: None,
+                    dedup: false,
+                }).await
+            })
+        }).collect();
+        for task in tasks {
+            assert!(task.await.unwrap().unwrap().starts_with("https://a.mock.com/_mock/"));
+        }
+        assert_eq!(store.keys("test").len(), 4);
+
+        // 重新加载配置后新的请求使用新快照
+        clouder.init(None, config("b.mock.com")).unwrap();
+        assert_eq!(clouder.resolve("test", "_mock/a.jpg", &[]).unwrap(), "https://b.mock.com/_mock/a.jpg");
+    }
+
     #[test]
     fn test_resolve() {
-        let native = Box::new(MockNative::new());
-        let opts = ClouderOptions {
-            strategy: vec![Box::new(MockStrategy::new("mock"))],
-            native,
-        };
-        
-        let mut clouder = Clouder::new(opts);
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &MemoryStore::new()));
         clouder.init(None, serde_json::json!({
             "cloudSource": [{
                 "name": "_mock",
@@ -352,8 +661,14 @@ mod tests {
                 "cloudCfg": {
                     "mock": "size=100x100"
                 }
+            }, {
+                "name": "resize",
+                "params": [{ "name": "w" }, { "name": "h", "default": "100" }],
+                "cloudCfg": {
+                    "mock": "size={w}x{h}"
+                }
             }]
-        }));
+        })).unwrap();
 
         let result = clouder.resolve(
             "test",
@@ -365,17 +680,110 @@ mod tests {
         let url = result.unwrap();
         assert!(url.contains("cdn.mock.com"));
         assert!(url.contains("size=100x100"));
+
+        let process = resolver::process::Process::new().rotate(90);
+        assert!(clouder.resolve_process("test", "_mock/test.jpg", &[], &process).is_err());
+
+        let url = clouder.resolve("test", "_mock/test.jpg", &["resize(w=320,h=240)"]).unwrap();
+        assert!(url.ends_with("?size=320x240"));
+        let url = clouder.resolve("test", "_mock/test.jpg", &["resize(w=320)"]).unwrap();
+        assert!(url.ends_with("?size=320x100"));
+        assert!(clouder.resolve("test", "_mock/test.jpg", &["resize"]).is_err());
     }
 
     #[tokio::test]
-    async fn test_upload_with_auto_name() {
-        let native = Box::new(MockNative::new());
-        let opts = ClouderOptions {
-            strategy: vec![Box::new(MockStrategy::new("mock"))],
-            native,
+    async fn test_resolve_signed() {
+        let native = ScriptedNative::new();
+        native.on_request(|_| Ok(serde_json::json!({
+            "credentials": {
+                "tmpSecretId": "AKID",
+                "tmpSecretKey": "SECRET",
+                "sessionToken": "TOKEN"
+            },
+            "expireAt": chrono::Utc::now().timestamp() + 3600
+        })));
+        let clouder = Clouder::new(ClouderOptions::from_native(vec![Box::new(MemoryStrategy::new("cos", MemoryStore::new()))], native));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_cos",
+                "cloud": "cos",
+                "buckets": [{
+                    "name": "disk",
+                    "domain": "disk-1250000000.cos.ap-beijing.myqcloud.com",
+                    "cloudName": "_cos",
+                    "cloud": "cos"
+                }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+
+        let url = clouder.resolve_signed("disk", "_cos/a.pdf", &[], 600).await.unwrap();
+        assert!(url.starts_with("https://disk-1250000000.cos.ap-beijing.myqcloud.com/_cos/a.pdf?q-sign-algorithm=sha1&q-ak=AKID"));
+        assert!(url.ends_with("&x-cos-security-token=TOKEN"));
+    }
+
+    #[tokio::test]
+    async fn test_presign_upload() {
+        let native = ScriptedNative::new();
+        native.on_request(|_| Ok(serde_json::json!({
+            "credentials": { "accessKeyId": "AK", "accessKeySecret": "SK", "securityToken": "TOKEN" },
+            "expireAt": chrono::Utc::now().timestamp() + 100
+        })));
+        let clouder = Clouder::new(ClouderOptions::from_native(vec![Box::new(MemoryStrategy::new("oss", MemoryStore::new()))], native));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_main",
+                "cloud": "oss",
+                "buckets": [{ "name": "disk", "domain": "disk.oss-cn-hangzhou.aliyuncs.com" }]
+            }, {
+                "name": "_minio",
+                "cloud": "oss",
+                "buckets": [{ "name": "disk", "domain": "http://127.0.0.1:9000" }]
+            }, {
+                "name": "_qiniu",
+                "cloud": "qiniu",
+                "buckets": [{ "name": "disk", "domain": "disk.example.com" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        let opts = |cloud_name: &str| PresignUploadOptions {
+            cloud_name: Some(cloud_name.to_string()),
+            file_name: Some("photo.jpg".to_string()),
+            openid: None,
+            content_type: None,
+            expires: 600,
         };
-        
-        let mut clouder = Clouder::new(opts);
+
+        // 带协议的域名不再加 https://
+        let presigned = clouder.presign_upload("disk", opts("_minio")).await.unwrap();
+        assert_eq!(presigned.url, "http://127.0.0.1:9000");
+        // 没有 POST policy 签名的云
+        let err = clouder.presign_upload("disk", opts("_qiniu")).await.unwrap_err();
+        assert!(matches!(err, XError::UnsupportedOperation { .. }));
+
+        let presigned = clouder.presign_upload("disk", PresignUploadOptions {
+            cloud_name: None,
+            file_name: Some("photo.jpg".to_string()),
+            openid: Some("u1".to_string()),
+            content_type: Some("image/jpeg".to_string()),
+            expires: 600,
+        }).await.unwrap();
+        assert_eq!(presigned.url, "https://disk.oss-cn-hangzhou.aliyuncs.com");
+        assert_eq!(presigned.method, "POST");
+        assert!(presigned.key.starts_with("_main/u1/") && presigned.key.ends_with(".jpg"));
+        assert_eq!(presigned.fields[0], ("key".to_string(), presigned.key.clone()));
+        assert!(presigned.fields.contains(&("OSSAccessKeyId".to_string(), "AK".to_string())));
+        assert!(presigned.fields.contains(&("x-oss-security-token".to_string(), "TOKEN".to_string())));
+        // 有效期被临时密钥的过期时间截断
+        assert!(presigned.expires_at <= chrono::Utc::now().timestamp() + 100);
+
+        let json = serde_json::to_value(&presigned).unwrap();
+        assert_eq!(json["fileField"], "file");
+    }
+
+    #[tokio::test]
+    async fn test_upload_with_auto_name() {
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &MemoryStore::new()));
         clouder.init(None, serde_json::json!({
             "cloudSource": [{
                 "name": "_mock",
@@ -389,18 +797,20 @@ mod tests {
                 }]
             }],
             "cloudMagics": []
-        }));
+        })).unwrap();
 
+        let path = fixture("upload.jpg", b"hello");
         let result = clouder.upload(
             "test",
-            "test.jpg",
-            utils::fill_name("test.jpg", "test_user"),
+            &path,
+            None,
             UploadOptions {
                 cloud_name: Some("_mock".to_string()),
                 on_progress: None,
                 disable_retry: false,
                 manual_retry: false,
                 openid: Some("test_user".to_string()),
+                dedup: false,
             }
         ).await;
 
@@ -411,14 +821,116 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_upload_with_retry() {
-        let native = Box::new(MockNative::new());
-        let opts = ClouderOptions {
-            strategy: vec![Box::new(MockStrategy::new("mock"))],
-            native,
+    async fn test_upload_with_key_template() {
+        let config = |template: &str| serde_json::json!({
+            "cloudSource": [{
+                "name": "_mock",
+                "cloud": "mock",
+                "buckets": [{
+                    "name": "test",
+                    "domain": "test.mock.com",
+                    "keyTemplate": template
+                }]
+            }],
+            "cloudMagics": []
+        });
+
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &MemoryStore::new()));
+        assert!(clouder.init(None, config("{cloud}/{openid}{ext}")).is_err());
+        clouder.init(None, config("{cloud}/{bucket}/{yyyy}/{openid}/{uid}{ext}")).unwrap();
+
+        let path = fixture("template.jpg", b"hello");
+        let url = clouder.upload(
+            "test",
+            &path,
+            None,
+            UploadOptions {
+                cloud_name: Some("_mock".to_string()),
+                on_progress: None,
+                disable_retry: false,
+                manual_retry: false,
+                openid: Some("test_user".to_string()),
+                dedup: false,
+            }
+        ).await.unwrap();
+
+        let prefix = format!("https://test.mock.com/_mock/test/{}/test_user/", chrono::Utc::now().format("%Y"));
+        assert!(url.starts_with(&prefix));
+        assert!(url.ends_with(".jpg"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_dedup() {
+        let file_path = fixture("dedup.jpg", b"same content");
+        let store = MemoryStore::new();
+        let config = serde_json::json!({
+            "cloudSource": [{
+                "name": "_mock",
+                "cloud": "mock",
+                "buckets": [{ "name": "test", "domain": "test.mock.com" }]
+            }],
+            "cloudMagics": []
+        });
+        let upload_opts = || UploadOptions {
+            cloud_name: Some("_mock".to_string()),
+            on_progress: None,
+            disable_retry: false,
+            manual_retry: false,
+            openid: None,
+            dedup: true,
         };
-        
-        let mut clouder = Clouder::new(opts);
+
+        let native = ScriptedNative::new();
+        let clouder = Clouder::new(mock_options(&native, &store));
+        clouder.init(None, config.clone()).unwrap();
+        let first = clouder.upload("test", &file_path, None, upload_opts()).await.unwrap();
+        // 对象已存在，跳过上传
+        let second = clouder.upload("test", &file_path, None, upload_opts()).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(native.uploads().len(), 1);
+
+        // 新的 native 没有缓存，通过 exists 发现对象已存在
+        let native = ScriptedNative::new();
+        let clouder = Clouder::new(mock_options(&native, &store));
+        clouder.init(None, config.clone()).unwrap();
+        let third = clouder.upload("test", &file_path, None, upload_opts()).await.unwrap();
+        assert_eq!(first, third);
+        assert!(native.uploads().is_empty());
+
+        // 命中缓存时不再询问策略
+        let key = format!("_mock/{}.jpg", key_template::content_hash(&file_path).unwrap());
+        store.remove("test", &key);
+        let cached = clouder.upload("test", &file_path, None, upload_opts()).await.unwrap();
+        assert_eq!(first, cached);
+        assert!(native.uploads().is_empty());
+
+        // 通过 Clouder 删除对象后缓存失效，重新上传
+        store.put("test", &key, b"same content".to_vec());
+        clouder.delete("test", &key).await.unwrap();
+        assert!(store.keys("test").is_empty());
+        let fourth = clouder.upload("test", &file_path, None, upload_opts()).await.unwrap();
+        assert_eq!(first, fourth);
+        assert_eq!(native.uploads().len(), 1);
+
+        let res = clouder.upload("test", &file_path, Some("a.jpg".to_string()), upload_opts()).await;
+        assert!(matches!(res, Err(XError::InvalidKey(_))));
+
+        // HEAD 失败时当作不存在，照常上传
+        let native = ScriptedNative::new();
+        let clouder = Clouder::new(mock_options(&native, &store));
+        clouder.init(None, config).unwrap();
+        store.set_unavailable(true);
+        assert_eq!(clouder.upload("test", &file_path, None, upload_opts()).await.unwrap(), first);
+        assert_eq!(native.uploads().len(), 1);
+
+        let hash = key_template::content_hash(&file_path).unwrap();
+        assert_eq!(first, format!("https://test.mock.com/_mock/{}.jpg", hash));
+        std::fs::remove_file(&file_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_with_retry() {
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &MemoryStore::new()));
         clouder.init(None, serde_json::json!({
             "cloudSource": [{
                 "name": "_mock",
@@ -443,33 +955,145 @@ mod tests {
                 }]
             }],
             "cloudMagics": []
-        }));
+        })).unwrap();
 
+        let path = fixture("upload.jpg", b"hello");
         let result = clouder.upload(
             "test",
-            "test.jpg",
-            "test.jpg".to_string(),
+            &path,
+            Some("test.jpg".to_string()),
             UploadOptions {
                 cloud_name: Some("_mock".to_string()),
                 on_progress: None,
                 disable_retry: false,
                 manual_retry: false,
                 openid: Some("test_user".to_string()),
+                dedup: false,
             }
         ).await;
 
         assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_key_operations() {
-        let native = Box::new(MockNative::new());
+    #[tokio::test]
+    async fn test_upload_metrics() {
+        let exporter = Arc::new(metrics::PrometheusExporter::new());
         let opts = ClouderOptions {
-            strategy: vec![Box::new(MockStrategy::new("mock"))],
-            native,
+            metrics: vec![exporter.clone()],
+            ..mock_options(&ScriptedNative::new(), &MemoryStore::new())
         };
-        
+
         let clouder = Clouder::new(opts);
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_mock",
+                "cloud": "mock",
+                "buckets": [{
+                    "name": "test",
+                    "domain": "test.mock.com",
+                    "cloudName": "_mock",
+                    "cloud": "mock"
+                }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+
+        let path = fixture("upload.jpg", b"hello");
+        let result = clouder.upload(
+            "test",
+            &path,
+            Some("test.jpg".to_string()),
+            UploadOptions {
+                cloud_name: Some("_mock".to_string()),
+                on_progress: None,
+                disable_retry: false,
+                manual_retry: false,
+                openid: None,
+                dedup: false,
+            }
+        ).await;
+        assert!(result.is_ok());
+
+        let text = exporter.render();
+        assert!(text.contains("xclouder_uploads_total{cloud_name=\"_mock\",cloud=\"mock\",bucket=\"test\"} 1"));
+        assert!(text.contains("xclouder_upload_success_total{cloud_name=\"_mock\",cloud=\"mock\",bucket=\"test\"} 1"));
+        assert!(text.contains("result=\"miss\""));
+    }
+
+    #[tokio::test]
+    async fn test_upload_metrics_on_config_error() {
+        let exporter = Arc::new(metrics::PrometheusExporter::new());
+        let native = ScriptedNative::new();
+        let clouder = Clouder::new(ClouderOptions {
+            metrics: vec![exporter.clone()],
+            ..mock_options(&native, &MemoryStore::new())
+        });
+        let path = fixture("metrics.jpg", b"hello");
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_other",
+                "cloud": "other",
+                "buckets": [{ "name": "test", "domain": "test.other.com" }]
+            }, {
+                "name": "_mock",
+                "cloud": "mock",
+                "buckets": [{ "name": "test", "domain": "test.mock.com" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        let opts = |cloud_name: &str| UploadOptions {
+            cloud_name: Some(cloud_name.to_string()),
+            on_progress: None,
+            disable_retry: false,
+            manual_retry: true,
+            openid: None,
+            dedup: false,
+        };
+
+        // 没有对应的策略
+        let result = clouder.upload("test", &path, Some("a.jpg".to_string()), opts("_other")).await;
+        assert!(matches!(result, Err(XError::CloudNotFound)));
+        let text = exporter.render();
+        assert!(text.contains("xclouder_uploads_total{cloud_name=\"_other\",cloud=\"other\",bucket=\"test\"} 1"));
+        assert!(text.contains("xclouder_upload_failures_total{cloud_name=\"_other\",cloud=\"other\",bucket=\"test\",error=\"cloud_not_found\"} 1"));
+
+        // 过期的临时凭证不算命中
+        native.set_storage("sts:_mock:test", serde_json::json!({ "expireAt": chrono::Utc::now().timestamp() - 1 }));
+        clouder.upload("test", &path, Some("a.jpg".to_string()), opts("_mock")).await.unwrap();
+        assert!(exporter.render().contains("xclouder_sts_cache_total{cloud_name=\"_mock\",cloud=\"mock\",bucket=\"test\",result=\"miss\"} 1"));
+    }
+
+    #[test]
+    fn test_resolve_process_skips_unsupported_fallback() {
+        let clouder = Clouder::new(ClouderOptions::from_native(vec![], ScriptedNative::new()));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_cos",
+                "cloud": "cos",
+                "buckets": [{ "name": "img", "domain": "img.cos.example.com", "fallback": "_azure" }]
+            }, {
+                "name": "_azure",
+                "cloud": "azure",
+                "buckets": [{ "name": "img", "domain": "acme.blob.core.windows.net", "container": "img" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        let process = resolver::process::Process::new().rotate(90);
+
+        let url = clouder.resolve_process("img", "_cos/a.jpg", &[], &process).unwrap();
+        assert_eq!(url, "https://img.cos.example.com/_cos/a.jpg?imageMogr2/rotate/90");
+
+        // azure 不支持图片处理，不能返回原图
+        clouder.mark_domain_unhealthy("img.cos.example.com").unwrap();
+        let res = clouder.resolve_process("img", "_cos/a.jpg", &[], &process);
+        assert!(matches!(res, Err(XError::NoAvailableDomain(_))));
+        let url = clouder.resolve("img", "_cos/a.jpg", &[]).unwrap();
+        assert_eq!(url, "https://acme.blob.core.windows.net/img/_cos/a.jpg");
+    }
+
+    #[test]
+    fn test_key_operations() {
+        let clouder = Clouder::new(mock_options(&ScriptedNative::new(), &MemoryStore::new()));
 
         assert!(clouder.is_xclouder("_mock/test.jpg"));
         assert!(!clouder.is_xclouder("test.jpg"));
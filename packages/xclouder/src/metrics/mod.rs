@@ -0,0 +1,64 @@
+mod prometheus;
+
+pub use prometheus::PrometheusExporter;
+
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Serialize;
+use crate::config::BucketSource;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct MetricLabels {
+    pub cloud_name: String,
+    pub cloud: String,
+    pub bucket: String,
+}
+
+impl MetricLabels {
+    pub fn from_bucket_source(bucket_source: &BucketSource) -> Self {
+        Self {
+            cloud_name: bucket_source.cloud_name.clone().unwrap_or_default(),
+            cloud: bucket_source.cloud.clone().unwrap_or_default(),
+            bucket: bucket_source.name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MetricEvent {
+    UploadBegin,
+    UploadSuccess { bytes: u64, duration: Duration },
+    UploadFailure { error: &'static str, duration: Duration },
+    Retry,
+    Fallback { to: MetricLabels },
+    StsCache { hit: bool },
+}
+
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, labels: &MetricLabels, event: &MetricEvent);
+}
+
+#[derive(Clone, Default)]
+pub struct Metrics {
+    sinks: Vec<Arc<dyn MetricsSink>>,
+}
+
+impl Metrics {
+    pub fn new(sinks: Vec<Arc<dyn MetricsSink>>) -> Self {
+        Self { sinks }
+    }
+
+    pub fn add_sink(&mut self, sink: Arc<dyn MetricsSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn record(&self, bucket_source: &BucketSource, event: MetricEvent) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let labels = MetricLabels::from_bucket_source(bucket_source);
+        for sink in &self.sinks {
+            sink.record(&labels, &event);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use super::{MetricEvent, MetricLabels, MetricsSink};
+
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (i, le) in DURATION_BUCKETS.iter().enumerate() {
+            if value <= *le {
+                self.buckets[i] += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    uploads: BTreeMap<MetricLabels, u64>,
+    successes: BTreeMap<MetricLabels, u64>,
+    failures: BTreeMap<(MetricLabels, &'static str), u64>,
+    bytes: BTreeMap<MetricLabels, u64>,
+    durations: BTreeMap<MetricLabels, Histogram>,
+    retries: BTreeMap<MetricLabels, u64>,
+    fallbacks: BTreeMap<(MetricLabels, String), u64>,
+    sts_cache: BTreeMap<(MetricLabels, &'static str), u64>,
+}
+
+// Prometheus 文本格式导出
+#[derive(Default)]
+pub struct PrometheusExporter {
+    registry: Mutex<Registry>,
+}
+
+impl PrometheusExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn render(&self) -> String {
+        let registry = match self.registry.lock() {
+            Ok(registry) => registry,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let mut out = String::new();
+
+        write_counter(&mut out, "xclouder_uploads_total", "Uploads started.",
+            registry.uploads.iter().map(|(l, v)| (labels(l, &[]), *v)));
+        write_counter(&mut out, "xclouder_upload_success_total", "Uploads finished successfully.",
+            registry.successes.iter().map(|(l, v)| (labels(l, &[]), *v)));
+        write_counter(&mut out, "xclouder_upload_failures_total", "Uploads failed, by error class.",
+            registry.failures.iter().map(|((l, e), v)| (labels(l, &[("error", e)]), *v)));
+        write_counter(&mut out, "xclouder_upload_bytes_total", "Bytes uploaded successfully.",
+            registry.bytes.iter().map(|(l, v)| (labels(l, &[]), *v)));
+        write_counter(&mut out, "xclouder_upload_retries_total", "Upload retries.",
+            registry.retries.iter().map(|(l, v)| (labels(l, &[]), *v)));
+        write_counter(&mut out, "xclouder_upload_fallbacks_total", "Fallbacks taken while uploading.",
+            registry.fallbacks.iter().map(|((l, to), v)| (labels(l, &[("to", to)]), *v)));
+        write_counter(&mut out, "xclouder_sts_cache_total", "STS cache lookups, by result.",
+            registry.sts_cache.iter().map(|((l, r), v)| (labels(l, &[("result", r)]), *v)));
+
+        let name = "xclouder_upload_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Upload duration in seconds.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (l, histogram) in &registry.durations {
+            for (i, le) in DURATION_BUCKETS.iter().enumerate() {
+                let le = le.to_string();
+                let _ = writeln!(out, "{}_bucket{} {}", name, labels(l, &[("le", &le)]), histogram.buckets[i]);
+            }
+            let _ = writeln!(out, "{}_bucket{} {}", name, labels(l, &[("le", "+Inf")]), histogram.count);
+            let _ = writeln!(out, "{}_sum{} {}", name, labels(l, &[]), histogram.sum);
+            let _ = writeln!(out, "{}_count{} {}", name, labels(l, &[]), histogram.count);
+        }
+
+        out
+    }
+}
+
+impl MetricsSink for PrometheusExporter {
+    fn record(&self, l: &MetricLabels, event: &MetricEvent) {
+        let mut registry = match self.registry.lock() {
+            Ok(registry) => registry,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match event {
+            MetricEvent::UploadBegin => {
+                *registry.uploads.entry(l.clone()).or_default() += 1;
+            }
+            MetricEvent::UploadSuccess { bytes, duration } => {
+                *registry.successes.entry(l.clone()).or_default() += 1;
+                *registry.bytes.entry(l.clone()).or_default() += bytes;
+                registry.durations.entry(l.clone()).or_default().observe(duration.as_secs_f64());
+            }
+            MetricEvent::UploadFailure { error, duration } => {
+                *registry.failures.entry((l.clone(), error)).or_default() += 1;
+                registry.durations.entry(l.clone()).or_default().observe(duration.as_secs_f64());
+            }
+            MetricEvent::Retry => {
+                *registry.retries.entry(l.clone()).or_default() += 1;
+            }
+            MetricEvent::Fallback { to } => {
+                *registry.fallbacks.entry((l.clone(), to.cloud_name.clone())).or_default() += 1;
+            }
+            MetricEvent::StsCache { hit } => {
+                let result = if *hit { "hit" } else { "miss" };
+                *registry.sts_cache.entry((l.clone(), result)).or_default() += 1;
+            }
+        }
+    }
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, samples: impl Iterator<Item = (String, u64)>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+fn labels(l: &MetricLabels, extra: &[(&str, &str)]) -> String {
+    let mut pairs = vec![
+        ("cloud_name", l.cloud_name.as_str()),
+        ("cloud", l.cloud.as_str()),
+        ("bucket", l.bucket.as_str()),
+    ];
+    pairs.extend_from_slice(extra);
+    let body = pairs.iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{{}}}", body)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let exporter = PrometheusExporter::new();
+        let labels = MetricLabels {
+            cloud_name: "_cos".to_string(),
+            cloud: "cos".to_string(),
+            bucket: "img".to_string(),
+        };
+
+        exporter.record(&labels, &MetricEvent::UploadBegin);
+        exporter.record(&labels, &MetricEvent::StsCache { hit: false });
+        exporter.record(&labels, &MetricEvent::UploadSuccess { bytes: 1024, duration: Duration::from_millis(300) });
+        exporter.record(&labels, &MetricEvent::UploadFailure { error: "network", duration: Duration::from_secs(3) });
+
+        let text = exporter.render();
+        assert!(text.contains("xclouder_uploads_total{cloud_name=\"_cos\",cloud=\"cos\",bucket=\"img\"} 1"));
+        assert!(text.contains("xclouder_upload_bytes_total{cloud_name=\"_cos\",cloud=\"cos\",bucket=\"img\"} 1024"));
+        assert!(text.contains("xclouder_upload_failures_total{cloud_name=\"_cos\",cloud=\"cos\",bucket=\"img\",error=\"network\"} 1"));
+        assert!(text.contains("xclouder_sts_cache_total{cloud_name=\"_cos\",cloud=\"cos\",bucket=\"img\",result=\"miss\"} 1"));
+        assert!(text.contains("xclouder_upload_duration_seconds_bucket{cloud_name=\"_cos\",cloud=\"cos\",bucket=\"img\",le=\"0.5\"} 1"));
+        assert!(text.contains("xclouder_upload_duration_seconds_count{cloud_name=\"_cos\",cloud=\"cos\",bucket=\"img\"} 2"));
+    }
+}
@@ -0,0 +1,301 @@
+use futures::stream::{self, StreamExt};
+use std::sync::Arc;
+use crate::cloud_client::{ConfigSnapshot, UploadOpts};
+use crate::config::BucketSource;
+use crate::error::{XError, XResult};
+use crate::strategy::ObjectMeta;
+use crate::utils::{file_md5, plain_md5};
+use crate::{unique_id, Clouder, DownloadArgs, XKey};
+
+pub struct MigrateOptions {
+    // 目标云源，如 _tos
+    pub to: String,
+    pub concurrency: usize,
+    // 迁移后比对大小和 MD5 ETag
+    pub verify: bool,
+    // 进度按作业记录，同一作业重跑时跳过已完成的对象
+    pub job: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migrated {
+    pub from: String,
+    pub to: String,
+    pub server_side: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct MigrateReport {
+    pub migrated: Vec<Migrated>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<(String, XError)>,
+}
+
+enum Outcome {
+    Migrated(Migrated),
+    Skipped(String),
+    Failed(String, XError),
+}
+
+// _cos/owner/a.jpg -> _tos/owner/a.jpg，旧格式 key 直接补上云源前缀
+pub fn rewrite_key(key: &str, to: &str) -> XResult<String> {
+    Ok(XKey::parse(key)?.with_cloud(to)?.object_key())
+}
+
+pub fn progress_key(job: &str, key: &str) -> String {
+    format!("migrate:{}:{}", job, key)
+}
+
+fn verify(expected: &ObjectMeta, actual: &ObjectMeta) -> XResult<()> {
+    if expected.size != actual.size {
+        return Err(XError::ChecksumMismatch(format!("{} size {} != {}", actual.key, actual.size, expected.size)));
+    }
+    if let (Some(expected), Some(actual_md5)) = (plain_md5(expected.etag.as_deref()), plain_md5(actual.etag.as_deref())) {
+        if expected != actual_md5 {
+            return Err(XError::ChecksumMismatch(format!("{} etag {} != {}", actual.key, actual_md5, expected)));
+        }
+    }
+    Ok(())
+}
+
+impl Clouder {
+    // 按 xclouder key 把对象迁到 opts.to，单个对象失败不影响其它对象
+    pub async fn migrate(&self, bucket: &str, keys: &[&str], opts: &MigrateOptions) -> XResult<MigrateReport> {
+        let snapshot = self.client.snapshot()?;
+        let target = snapshot.cloud_bucket_source(bucket, &opts.to)?;
+        let job = opts.job.clone().unwrap_or_else(|| format!("{}:{}", bucket, opts.to));
+
+        let outcomes: Vec<Outcome> = stream::iter(keys.iter().map(|key| key.to_string()))
+            .map(|key| self.migrate_one(&snapshot, bucket, key, target, &job, opts))
+            .buffer_unordered(opts.concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut report = MigrateReport::default();
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Migrated(migrated) => report.migrated.push(migrated),
+                Outcome::Skipped(key) => report.skipped.push(key),
+                Outcome::Failed(key, err) => report.failed.push((key, err)),
+            }
+        }
+        Ok(report)
+    }
+
+    pub async fn migrate_prefix(&self, bucket: &str, prefix: &str, opts: &MigrateOptions) -> XResult<MigrateReport> {
+        let objects = self.list(bucket, prefix).await?;
+        let keys: Vec<&str> = objects.iter().map(|object| object.key.as_str()).collect();
+        self.migrate(bucket, &keys, opts).await
+    }
+
+    async fn migrate_one(&self, snapshot: &ConfigSnapshot, bucket: &str, key: String, target: &BucketSource, job: &str, opts: &MigrateOptions) -> Outcome {
+        let progress = progress_key(job, &key);
+        if self.client.native.get_storage(&progress).is_some() {
+            return Outcome::Skipped(key);
+        }
+        match self.migrate_object(snapshot, bucket, &key, target, opts).await {
+            Ok(migrated) => {
+                self.client.native.set_storage(&progress, serde_json::json!({
+                    "to": migrated.to,
+                    "at": chrono::Utc::now().timestamp(),
+                }));
+                Outcome::Migrated(migrated)
+            }
+            Err(err) => Outcome::Failed(key, err),
+        }
+    }
+
+    async fn migrate_object(&self, snapshot: &ConfigSnapshot, bucket: &str, key: &str, target: &BucketSource, opts: &MigrateOptions) -> XResult<Migrated> {
+        let (source, src_key) = snapshot.key_bucket_source(bucket, key)?;
+        if source.cloud_name == target.cloud_name {
+            return Err(XError::InvalidKey(format!("{} is already in {}", key, opts.to)));
+        }
+        let dest_key = rewrite_key(key, &opts.to)?;
+        let source_strategy = self.client.bucket_strategy(source)?;
+        let target_strategy = self.client.bucket_strategy(target)?;
+        let meta = source_strategy.head(source, &src_key).await?;
+
+        // 同一家云走服务端复制，不经过本机
+        let server_side = source.cloud == target.cloud;
+        if server_side {
+            target_strategy.copy(source, &src_key, target, &dest_key).await?;
+        } else {
+            let file_path = std::env::temp_dir()
+                .join(format!("xclouder-migrate-{}", unique_id::next_id()))
+                .to_string_lossy()
+                .to_string();
+            let res = self.transfer(bucket, &src_key, &meta, target, &dest_key, &file_path, opts.verify).await;
+            let _ = std::fs::remove_file(&file_path);
+            res?;
+        }
+
+        if opts.verify {
+            let copied = target_strategy.head(target, &dest_key).await?;
+            verify(&meta, &copied)?;
+        }
+        Ok(Migrated {
+            from: src_key,
+            to: dest_key,
+            server_side,
+        })
+    }
+
+    // 跨云：下载到临时文件，校验后上传到目标云源
+    #[allow(clippy::too_many_arguments)]
+    async fn transfer(
+        &self,
+        bucket: &str,
+        src_key: &str,
+        meta: &ObjectMeta,
+        target: &BucketSource,
+        dest_key: &str,
+        file_path: &str,
+        verify: bool,
+    ) -> XResult<()> {
+        let url = self.resolve_signed(bucket, src_key, &[], 3600).await?;
+        self.client.native.download_file(DownloadArgs {
+            url,
+            file_path: file_path.to_string(),
+            headers: vec![],
+            offset: 0,
+            on_progress: None,
+        }).await?;
+
+        if verify {
+            if let Some(expected) = plain_md5(meta.etag.as_deref()) {
+                let actual = file_md5(file_path)?;
+                if actual != expected {
+                    return Err(XError::ChecksumMismatch(format!("{} downloaded md5 {} != {}", src_key, actual, expected)));
+                }
+            }
+        }
+
+        let strategy = self.client.bucket_strategy(target)?;
+        let sts = strategy.get_sts(target).await?;
+        strategy.upload(target, sts, &UploadOpts {
+            bucket_source: Arc::new(target.clone()),
+            bucket: bucket.to_string(),
+            filename: XKey::parse(dest_key)?.path().to_string(),
+            file_path: file_path.to_string(),
+            key: dest_key.to_string(),
+            on_progress: None,
+            up_id: chrono::Utc::now().timestamp_millis(),
+            disable_retry: true,
+            manual_retry: false,
+        }).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MemoryStore, MemoryStrategy, ScriptedNative};
+    use crate::ClouderOptions;
+
+    // 按下载地址从内存存储取对象，corrupt 时多写一个字节模拟传输损坏
+    fn mem_native(store: &Arc<MemoryStore>, corrupt: bool) -> Arc<ScriptedNative> {
+        let native = ScriptedNative::new();
+        let store = store.clone();
+        native.on_download(move |args| {
+            let path = args.url.trim_start_matches("https://").split('?').next().unwrap();
+            let (_, key) = path.split_once('/').unwrap();
+            let mut data = store.get("disk", key).ok_or(XError::NotFound(key.to_string()))?;
+            if corrupt {
+                data.push(b'!');
+            }
+            std::fs::write(&args.file_path, data).unwrap();
+            Ok(())
+        });
+        native
+    }
+
+    fn clouder(store: &Arc<MemoryStore>, native: &Arc<ScriptedNative>) -> Clouder {
+        let clouder = Clouder::new(ClouderOptions::from_native(
+            vec![
+                Box::new(MemoryStrategy::new("cos", store.clone())),
+                Box::new(MemoryStrategy::new("tos", store.clone())),
+            ],
+            native.clone(),
+        ));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [
+                { "name": "_cos", "cloud": "cos", "buckets": [{ "name": "disk", "domain": "disk.cos.ap-beijing.myqcloud.com" }] },
+                { "name": "_tos", "cloud": "tos", "buckets": [{ "name": "disk", "domain": "disk.tos-cn-beijing.volces.com" }] },
+                { "name": "_tos2", "cloud": "tos", "buckets": [{ "name": "disk", "domain": "disk2.tos-cn-beijing.volces.com" }] }
+            ],
+            "cloudMagics": []
+        })).unwrap();
+        clouder
+    }
+
+    fn options(to: &str) -> MigrateOptions {
+        MigrateOptions {
+            to: to.to_string(),
+            concurrency: 2,
+            verify: true,
+            job: None,
+        }
+    }
+
+    #[test]
+    fn test_rewrite_key() {
+        assert_eq!(rewrite_key("_cos/u1/a.jpg?imageMogr2", "_tos").unwrap(), "_tos/u1/a.jpg");
+        assert_eq!(rewrite_key("u1/a.jpg", "_tos").unwrap(), "_tos/u1/a.jpg");
+        assert!(rewrite_key("_cos/u1/a.jpg", "tos").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let store = MemoryStore::new();
+        for name in ["a", "b", "c"] {
+            store.put("disk", &format!("_cos/u1/{}.jpg", name), name.repeat(10).into_bytes());
+        }
+        let native = mem_native(&store, false);
+        let clouder = clouder(&store, &native);
+
+        // 跨云：下载再上传
+        let keys = ["_cos/u1/a.jpg", "_cos/u1/b.jpg", "_cos/u1/missing.jpg"];
+        let report = clouder.migrate("disk", &keys, &options("_tos")).await.unwrap();
+        assert_eq!(report.migrated.len(), 2);
+        assert!(report.migrated.iter().all(|m| !m.server_side));
+        assert_eq!(report.failed.len(), 1);
+        assert!(matches!(report.failed[0].1, XError::NotFound(_)));
+        assert_eq!(store.get("disk", "_tos/u1/a.jpg").unwrap(), b"aaaaaaaaaa");
+        native.assert_attempted(&["disk.tos-cn-beijing.volces.com", "disk.tos-cn-beijing.volces.com"]);
+        assert!(native.storage_keys().contains(&progress_key("disk:_tos", "_cos/u1/a.jpg")));
+
+        // 重跑时跳过已完成的对象
+        let report = clouder.migrate_prefix("disk", "_cos/u1/", &options("_tos")).await.unwrap();
+        report.skipped.iter().for_each(|key| assert!(key == "_cos/u1/a.jpg" || key == "_cos/u1/b.jpg"));
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(report.migrated.len(), 1);
+        assert_eq!(report.migrated[0].to, "_tos/u1/c.jpg");
+
+        // 同一家云：服务端复制，不经过本机下载和上传
+        let (downloads, uploads) = (native.downloads().len(), native.uploads().len());
+        let report = clouder.migrate("disk", &["_tos/u1/a.jpg"], &options("_tos2")).await.unwrap();
+        assert_eq!(report.migrated, vec![Migrated {
+            from: "_tos/u1/a.jpg".to_string(),
+            to: "_tos2/u1/a.jpg".to_string(),
+            server_side: true,
+        }]);
+        assert_eq!(native.downloads().len(), downloads);
+        assert_eq!(native.uploads().len(), uploads);
+        assert_eq!(store.get("disk", "_tos2/u1/a.jpg").unwrap(), b"aaaaaaaaaa");
+    }
+
+    #[tokio::test]
+    async fn test_migrate_checksum_mismatch() {
+        let store = MemoryStore::new();
+        store.put("disk", "_cos/u1/a.jpg", b"hello".to_vec());
+        let native = mem_native(&store, true);
+        let clouder = clouder(&store, &native);
+
+        let report = clouder.migrate("disk", &["_cos/u1/a.jpg"], &options("_tos")).await.unwrap();
+        assert!(matches!(report.failed[0].1, XError::ChecksumMismatch(_)));
+        assert!(store.get("disk", "_tos/u1/a.jpg").is_none());
+        assert!(native.uploads().is_empty());
+        assert!(!native.storage_keys().iter().any(|key| key.starts_with("migrate:")));
+    }
+}
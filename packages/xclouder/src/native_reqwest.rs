@@ -0,0 +1,489 @@
+// 基于 reqwest 的默认 Native（native-reqwest feature），适用于服务端和命令行：
+// 相对地址的请求拼接 api_base，存储写入本地 JSON 文件，网络状态由可达性探测得出
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use crate::error::{XError, XResult};
+use crate::{DownloadArgs, Native, NetworkInfo, RequestArgs, UploadArgs, UploadPartArgs};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct ReqwestNative {
+    client: Client,
+    api_base: String,
+    fallback_base: String,
+    probe_urls: Vec<String>,
+    storage_path: Option<PathBuf>,
+    storage: Mutex<HashMap<String, Value>>,
+    // enable_cache 的 GET 请求结果，进程内有效
+    cache: Mutex<HashMap<String, Value>>,
+}
+
+impl ReqwestNative {
+    pub fn new(api_base: &str) -> Self {
+        let api_base = api_base.trim_end_matches('/').to_string();
+        Self {
+            client: Client::new(),
+            fallback_base: format!("{}/api/cloud/fallback", api_base),
+            probe_urls: vec![api_base.clone()],
+            api_base,
+            storage_path: None,
+            storage: Mutex::new(HashMap::new()),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    // 存储持久化到 JSON 文件，已有内容会先读入；文件损坏时从空存储开始
+    pub fn with_storage_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let storage = std::fs::read(&path).ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        self.storage = Mutex::new(storage);
+        self.storage_path = Some(path);
+        self
+    }
+
+    // 探测地址，任意一个可达即认为网络可用，默认探测 api_base
+    pub fn with_probe_urls(mut self, urls: Vec<String>) -> Self {
+        self.probe_urls = urls;
+        self
+    }
+
+    // 没有可用域名时的兜底地址前缀，结果为 {base}/{bucket}/{key}
+    pub fn with_fallback_base(mut self, base: &str) -> Self {
+        self.fallback_base = base.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn url(&self, url: &str) -> String {
+        if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("{}/{}", self.api_base, url.trim_start_matches('/'))
+        }
+    }
+
+    fn builder(&self, method: &str, url: &str, headers: &[(String, String)]) -> XResult<RequestBuilder> {
+        let method = Method::from_bytes(method.to_uppercase().as_bytes())
+            .map_err(|_| XError::RequestFailed(format!("invalid method {}", method)))?;
+        let mut builder = self.client.request(method, self.url(url));
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        Ok(builder)
+    }
+
+    fn save_storage(&self, storage: &HashMap<String, Value>) {
+        let Some(path) = &self.storage_path else { return };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        // 先写临时文件再改名，避免进程中断时留下半个文件
+        let tmp = path.with_extension("tmp");
+        if let Ok(data) = serde_json::to_vec(storage) {
+            if std::fs::write(&tmp, data).is_ok() {
+                let _ = std::fs::rename(&tmp, path);
+            }
+        }
+    }
+
+    async fn probe(&self, url: &str) -> Result<(), String> {
+        self.client.head(url).timeout(PROBE_TIMEOUT).send().await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn network_error(e: reqwest::Error) -> XError {
+    XError::NetworkError(e.to_string())
+}
+
+// 按 Native 约定处理响应：404 为 NotFound，headers 返回响应头对象
+async fn read_response(res: Response, response_type: &str) -> XResult<Value> {
+    let status = res.status();
+    let url = res.url().to_string();
+    if status == StatusCode::NOT_FOUND {
+        return Err(XError::NotFound(url));
+    }
+    // GCS 分片上传未完成时返回不带 Location 的 308，按成功处理，由调用方读取 Range
+    let resume_incomplete = status == StatusCode::PERMANENT_REDIRECT
+        && !res.headers().contains_key(reqwest::header::LOCATION)
+        && matches!(response_type, "headers" | "text");
+    if !status.is_success() && !resume_incomplete {
+        let body = res.text().await.unwrap_or_default();
+        return Err(XError::RequestFailed(format!("{} {}: {}", status.as_u16(), url, body)));
+    }
+    match response_type {
+        "headers" => Ok(Value::Object(res.headers().iter()
+            .map(|(name, value)| (name.to_string(), Value::String(value.to_str().unwrap_or_default().to_string())))
+            .collect())),
+        "text" => Ok(Value::String(res.text().await.map_err(network_error)?)),
+        _ => {
+            let body = res.bytes().await.map_err(network_error)?;
+            if body.is_empty() {
+                return Ok(Value::Null);
+            }
+            serde_json::from_slice(&body).map_err(|e| XError::SerdeError(e.to_string()))
+        }
+    }
+}
+
+fn form_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn report(on_progress: &Option<Arc<dyn Fn(f32) + Send + Sync>>, sent: u64, total: u64) {
+    if let Some(on_progress) = on_progress {
+        on_progress(if total == 0 { 1.0 } else { sent as f32 / total as f32 });
+    }
+}
+
+fn has_system_proxy() -> bool {
+    ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+        .iter()
+        .any(|name| std::env::var(name).is_ok_and(|v| !v.is_empty()))
+}
+
+fn host(url: &str) -> Option<(String, u16)> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some((url.host_str()?.to_string(), url.port_or_known_default()?))
+}
+
+#[async_trait]
+impl Native for ReqwestNative {
+    async fn upload_file(&self, args: UploadArgs) -> XResult<()> {
+        let file = tokio::fs::File::open(&args.file_path).await
+            .map_err(|e| XError::UploadFailed(format!("{}: {}", args.file_path, e)))?;
+        let total = file.metadata().await.map_err(|e| XError::UploadFailed(e.to_string()))?.len();
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let on_progress = args.on_progress.clone();
+        let stream = ReaderStream::new(file).inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                let sent = sent.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+                report(&on_progress, sent, total);
+            }
+        });
+        let file_name = std::path::Path::new(&args.file_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".to_string());
+        let part = Part::stream_with_length(reqwest::Body::wrap_stream(stream), total).file_name(file_name);
+
+        // 表单字段必须在文件之前，部分云厂商只解析文件之前的字段
+        let mut form = Form::new();
+        if let Some(fields) = args.form_data.as_object() {
+            for (name, value) in fields {
+                form = form.text(name.clone(), form_value(value));
+            }
+        }
+        let form = form.part(args.name, part);
+
+        let res = self.client.post(self.url(&args.url)).multipart(form).send().await.map_err(network_error)?;
+        let status = res.status();
+        if !status.is_success() {
+            let body = res.text().await.unwrap_or_default();
+            return Err(XError::UploadFailed(format!("{}: {}", status.as_u16(), body)));
+        }
+        Ok(())
+    }
+
+    async fn download_file(&self, args: DownloadArgs) -> XResult<()> {
+        let mut res = self.builder("GET", &args.url, &args.headers)?.send().await.map_err(network_error)?;
+        let status = res.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(XError::NotFound(args.url));
+        }
+        if !status.is_success() {
+            return Err(XError::RequestFailed(format!("{} {}", status.as_u16(), args.url)));
+        }
+
+        let resume = args.offset > 0 && status == StatusCode::PARTIAL_CONTENT;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resume)
+            .truncate(!resume)
+            .open(&args.file_path).await
+            .map_err(|e| XError::RequestFailed(format!("{}: {}", args.file_path, e)))?;
+        let start = if resume { args.offset } else { 0 };
+        let total = res.content_length().map(|len| start + len).unwrap_or(0);
+        let mut written = start;
+        while let Some(chunk) = res.chunk().await.map_err(network_error)? {
+            file.write_all(&chunk).await.map_err(|e| XError::RequestFailed(e.to_string()))?;
+            written += chunk.len() as u64;
+            if total > 0 {
+                report(&args.on_progress, written, total);
+            }
+        }
+        file.flush().await.map_err(|e| XError::RequestFailed(e.to_string()))?;
+        report(&args.on_progress, 1, 1);
+        Ok(())
+    }
+
+    async fn upload_part(&self, args: UploadPartArgs) -> XResult<Value> {
+        let mut file = tokio::fs::File::open(&args.file_path).await
+            .map_err(|e| XError::UploadFailed(format!("{}: {}", args.file_path, e)))?;
+        file.seek(std::io::SeekFrom::Start(args.offset)).await
+            .map_err(|e| XError::UploadFailed(e.to_string()))?;
+        let mut body = vec![0; args.size as usize];
+        file.read_exact(&mut body).await.map_err(|e| XError::UploadFailed(e.to_string()))?;
+
+        let res = self.builder(&args.method, &args.url, &args.headers)?
+            .body(body)
+            .send().await
+            .map_err(network_error)?;
+        read_response(res, &args.response_type).await
+    }
+
+    async fn request(&self, args: RequestArgs) -> XResult<Value> {
+        let cacheable = args.enable_cache && args.method.eq_ignore_ascii_case("GET");
+        let url = self.url(&args.url);
+        if cacheable {
+            if let Some(value) = self.cache.lock().ok().and_then(|cache| cache.get(&url).cloned()) {
+                return Ok(value);
+            }
+        }
+
+        let mut builder = self.builder(&args.method, &args.url, &args.headers)?;
+        if args.timeout > 0 {
+            builder = builder.timeout(Duration::from_millis(args.timeout as u64));
+        }
+        if let Some(body) = args.body {
+            builder = builder.body(body);
+        }
+        let res = builder.send().await.map_err(network_error)?;
+        let value = read_response(res, &args.response_type).await?;
+        if cacheable {
+            if let Ok(mut cache) = self.cache.lock() {
+                cache.insert(url, value.clone());
+            }
+        }
+        Ok(value)
+    }
+
+    fn set_storage(&self, key: &str, value: Value) {
+        if let Ok(mut storage) = self.storage.lock() {
+            storage.insert(key.to_string(), value);
+            self.save_storage(&storage);
+        }
+    }
+
+    fn get_storage(&self, key: &str) -> Option<Value> {
+        self.storage.lock().ok()?.get(key).cloned()
+    }
+
+    fn del_storage(&self, key: &str) {
+        if let Ok(mut storage) = self.storage.lock() {
+            if storage.remove(key).is_some() {
+                self.save_storage(&storage);
+            }
+        }
+    }
+
+    fn resolve_fallback(&self, bucket: &str, key: &str) -> String {
+        format!("{}/{}/{}", self.fallback_base, bucket, key)
+    }
+
+    // 服务端拿不到信号强度和网络制式，可达时报告为 ethernet，全部探测失败时为 none
+    async fn check_network(&self) -> XResult<NetworkInfo> {
+        let mut dns_error = false;
+        let mut check_error = None;
+        for url in &self.probe_urls {
+            if let Some((host, port)) = host(url) {
+                if tokio::net::lookup_host((host.as_str(), port)).await.is_err() {
+                    dns_error = true;
+                    check_error = Some(format!("dns lookup failed: {}", host));
+                    continue;
+                }
+            }
+            match self.probe(url).await {
+                Ok(()) => {
+                    return Ok(NetworkInfo {
+                        has_system_proxy: has_system_proxy(),
+                        signal_strength: 100,
+                        network_type: "ethernet".to_string(),
+                        dns_error,
+                        check_error: None,
+                    });
+                }
+                Err(e) => check_error = Some(e),
+            }
+        }
+        Ok(NetworkInfo {
+            has_system_proxy: has_system_proxy(),
+            signal_strength: 0,
+            network_type: "none".to_string(),
+            dns_error,
+            check_error,
+        })
+    }
+
+    async fn check_dns(&self, domain: &str) -> XResult<bool> {
+        let domain = host(&format!("https://{}", domain.split_once("://").map(|(_, d)| d).unwrap_or(domain)))
+            .map(|(host, _)| host)
+            .unwrap_or_else(|| domain.to_string());
+        Ok(tokio::net::lookup_host((domain.as_str(), 443)).await
+            .map(|mut addrs| addrs.next().is_some())
+            .unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    // 每个连接只处理一个请求，返回收到的原始请求
+    async fn serve(responses: Vec<&'static str>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut raw = Vec::new();
+                let mut buf = [0; 4096];
+                loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end].lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        if raw.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                    if n == 0 {
+                        break;
+                    }
+                }
+                requests.push(String::from_utf8_lossy(&raw).to_string());
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (base, handle)
+    }
+
+    fn request(url: &str, response_type: &str) -> RequestArgs {
+        RequestArgs {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            enable_cache: false,
+            timeout: 10000,
+            response_type: response_type.to_string(),
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request() {
+        let (base, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 16\r\nConnection: close\r\n\r\n{\"token\":\"sts\"}\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let native = ReqwestNative::new(&format!("{}/", base));
+
+        let value = native.request(request("/api/cloud/sts?cloud=cos", "json")).await.unwrap();
+        assert_eq!(value["token"], "sts");
+        let res = native.request(request("/missing", "json")).await;
+        assert!(matches!(res, Err(XError::NotFound(_))));
+        let headers = native.request(request(&format!("{}/a.jpg", base), "headers")).await.unwrap();
+        assert_eq!(crate::strategy::header_value(&headers, "ETag").as_deref(), Some("\"abc\""));
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /api/cloud/sts?cloud=cos HTTP/1.1"));
+        assert!(requests[2].starts_with("GET /a.jpg HTTP/1.1"));
+    }
+
+    #[tokio::test]
+    async fn test_resume_incomplete() {
+        let (base, server) = serve(vec![
+            "HTTP/1.1 308 Resume Incomplete\r\nRange: bytes=0-4\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 308 Resume Incomplete\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 308 Resume Incomplete\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let native = ReqwestNative::new(&base);
+
+        let headers = native.request(request(&format!("{}/session", base), "headers")).await.unwrap();
+        assert_eq!(crate::strategy::header_value(&headers, "Range").as_deref(), Some("bytes=0-4"));
+        let text = native.request(request(&format!("{}/session", base), "text")).await.unwrap();
+        assert_eq!(text, "");
+        let res = native.request(request(&format!("{}/session", base), "json")).await;
+        assert!(matches!(res, Err(XError::RequestFailed(_))));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_file() {
+        let (base, server) = serve(vec![
+            "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+        ]).await;
+        let path = std::env::temp_dir().join(format!("xclouder-reqwest-{}.jpg", std::process::id()));
+        std::fs::write(&path, b"hello").unwrap();
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let recorded = progress.clone();
+
+        ReqwestNative::new(&base).upload_file(UploadArgs {
+            url: base.clone(),
+            name: "file".to_string(),
+            file_path: path.to_string_lossy().to_string(),
+            form_data: serde_json::json!({ "key": "_cos/a.jpg", "success_action_status": 200 }),
+            on_progress: Some(Arc::new(move |p| recorded.lock().unwrap().push(p))),
+        }).await.unwrap();
+
+        let raw = server.await.unwrap().remove(0);
+        let key = raw.find("name=\"key\"\r\n\r\n_cos/a.jpg").unwrap();
+        let status = raw.find("name=\"success_action_status\"\r\n\r\n200").unwrap();
+        let file = raw.find("name=\"file\"; filename=").unwrap();
+        assert!(key < file && status < file);
+        assert!(raw[file..].contains("hello"));
+        assert_eq!(progress.lock().unwrap().last(), Some(&1.0));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_storage_and_dns() {
+        let path = std::env::temp_dir().join(format!("xclouder-reqwest-{}", std::process::id())).join("storage.json");
+        let native = ReqwestNative::new("http://127.0.0.1:1").with_storage_path(&path);
+        native.set_storage("sts:_cos:img", serde_json::json!({ "token": "a" }));
+        native.set_storage("tmp", serde_json::json!(1));
+        native.del_storage("tmp");
+
+        let reloaded = ReqwestNative::new("http://127.0.0.1:1").with_storage_path(&path);
+        assert_eq!(reloaded.get_storage("sts:_cos:img"), Some(serde_json::json!({ "token": "a" })));
+        assert_eq!(reloaded.get_storage("tmp"), None);
+        assert_eq!(reloaded.resolve_fallback("img", "_cos/a.jpg"), "http://127.0.0.1:1/api/cloud/fallback/img/_cos/a.jpg");
+
+        assert!(native.check_dns("localhost").await.unwrap());
+        assert!(!native.check_dns("xclouder.invalid").await.unwrap());
+        let info = native.check_network().await.unwrap();
+        assert_eq!(info.network_type, "none");
+        assert!(info.check_error.is_some());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,143 @@
+use crate::error::{XError, XResult};
+
+// 按云厂商语义合并多个魔法参数：
+// oss/tos 合并到同一个 x-*-process 参数下，cos 和七牛的处理链用 | 串联
+pub fn compose(cloud: &str, parts: &[String]) -> XResult<Vec<String>> {
+    let params: Vec<(&str, Option<&str>)> = parts.iter()
+        .flat_map(|part| part.split('&'))
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once('=') {
+            Some((k, v)) => (k, Some(v)),
+            None => (p, None),
+        })
+        .collect();
+
+    match cloud {
+        "oss" => compose_process(cloud, "x-oss-process", &params),
+        "tos" => compose_process(cloud, "x-tos-process", &params),
+        "cos" | "qiniu" => compose_pipeline(cloud, &params),
+        _ => Ok(parts.iter().filter(|p| !p.is_empty()).cloned().collect()),
+    }
+}
+
+fn conflict(cloud: &str, detail: String) -> XError {
+    XError::MagicError(format!("cannot merge magics for {}: {}", cloud, detail))
+}
+
+fn push_plain<'a>(cloud: &str, plain: &mut Vec<(&'a str, Option<&'a str>)>, k: &'a str, v: Option<&'a str>) -> XResult<()> {
+    if let Some((_, existing)) = plain.iter().find(|(name, _)| *name == k) {
+        if *existing != v {
+            return Err(conflict(cloud, format!("conflicting values for {}", k)));
+        }
+        return Ok(());
+    }
+    plain.push((k, v));
+    Ok(())
+}
+
+fn format_plain(plain: &[(&str, Option<&str>)]) -> Vec<String> {
+    plain.iter()
+        .map(|(k, v)| match v {
+            Some(v) => format!("{}={}", k, v),
+            None => k.to_string(),
+        })
+        .collect()
+}
+
+fn compose_process(cloud: &str, name: &str, params: &[(&str, Option<&str>)]) -> XResult<Vec<String>> {
+    let mut category: Option<&str> = None;
+    let mut steps = Vec::new();
+    let mut plain = Vec::new();
+
+    for (k, v) in params {
+        if *k != name {
+            push_plain(cloud, &mut plain, k, *v)?;
+            continue;
+        }
+        let value = v.unwrap_or("");
+        let (kind, rest) = value.split_once('/').unwrap_or((value, ""));
+        // style 为控制台预设的样式，无法与其他处理合并
+        if kind == "style" && (category.is_some() || params.iter().filter(|(k, _)| *k == name).count() > 1) {
+            return Err(conflict(cloud, format!("{} cannot be combined", value)));
+        }
+        match category {
+            Some(existing) if existing != kind => {
+                return Err(conflict(cloud, format!("{} and {} processing", existing, kind)));
+            }
+            _ => category = Some(kind),
+        }
+        steps.extend(rest.split('/').filter(|s| !s.is_empty()));
+    }
+
+    let mut queries = Vec::new();
+    if let Some(kind) = category {
+        queries.push(format!("{}={}/{}", name, kind, steps.join("/")));
+    }
+    queries.extend(format_plain(&plain));
+    Ok(queries)
+}
+
+fn compose_pipeline(cloud: &str, params: &[(&str, Option<&str>)]) -> XResult<Vec<String>> {
+    let mut chain = Vec::new();
+    let mut plain = Vec::new();
+
+    for (k, v) in params {
+        // imageMogr2/... imageView2/... watermark/... 等为处理链，base64 内容里可能带有 =
+        if k.contains('/') || *k == "imageInfo" {
+            let raw = match v {
+                Some(v) => format!("{}={}", k, v),
+                None => k.to_string(),
+            };
+            chain.extend(raw.split('|').filter(|s| !s.is_empty()).map(|s| s.to_string()));
+        } else {
+            push_plain(cloud, &mut plain, k, *v)?;
+        }
+    }
+
+    // ci-process 会返回处理后的新对象，无法和图片处理链合并
+    if !chain.is_empty() && plain.iter().any(|(k, _)| *k == "ci-process") {
+        return Err(conflict(cloud, "ci-process and image processing".to_string()));
+    }
+
+    let mut queries = Vec::new();
+    if !chain.is_empty() {
+        queries.push(chain.join("|"));
+    }
+    queries.extend(format_plain(&plain));
+    Ok(queries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_compose() {
+        let oss = compose("oss", &parts(&["x-oss-process=image/resize,w_200", "x-oss-process=image/format,jpg"])).unwrap();
+        assert_eq!(oss, vec!["x-oss-process=image/resize,w_200/format,jpg"]);
+
+        let tos = compose("tos", &parts(&["x-tos-process=image/resize,w_200", "x-tos-process=image/quality,q_80"])).unwrap();
+        assert_eq!(tos, vec!["x-tos-process=image/resize,w_200/quality,q_80"]);
+
+        let cos = compose("cos", &parts(&["imageMogr2/thumbnail/200x", "imageMogr2/format/jpg|watermark/2/text/eA=="])).unwrap();
+        assert_eq!(cos, vec!["imageMogr2/thumbnail/200x|imageMogr2/format/jpg|watermark/2/text/eA=="]);
+
+        let qiniu = compose("qiniu", &parts(&["imageView2/2/w/200", "imageMogr2/format/webp"])).unwrap();
+        assert_eq!(qiniu, vec!["imageView2/2/w/200|imageMogr2/format/webp"]);
+
+        let mock = compose("mock", &parts(&["size=100", "q=80"])).unwrap();
+        assert_eq!(mock, vec!["size=100", "q=80"]);
+    }
+
+    #[test]
+    fn test_compose_conflict() {
+        assert!(compose("oss", &parts(&["x-oss-process=image/resize,w_200", "x-oss-process=video/snapshot,t_0"])).is_err());
+        assert!(compose("oss", &parts(&["x-oss-process=style/small", "x-oss-process=image/format,jpg"])).is_err());
+        assert!(compose("cos", &parts(&["imageMogr2/thumbnail/200x", "ci-process=snapshot&time=1"])).is_err());
+        assert!(compose("cos", &parts(&["ci-process=snapshot&time=1", "ci-process=snapshot&time=2"])).is_err());
+    }
+}
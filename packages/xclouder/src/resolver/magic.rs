@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use crate::config::CloudMagic;
+use crate::error::{XError, XResult};
+
+// 解析 "thumbnail(w=320,h=240)" 形式的魔法参数调用
+pub fn parse_call(call: &str) -> XResult<(&str, Vec<(&str, &str)>)> {
+    let call = call.trim();
+    let Some((name, rest)) = call.split_once('(') else {
+        return Ok((call, vec![]));
+    };
+    let body = rest.strip_suffix(')')
+        .ok_or_else(|| XError::MagicError(format!("unclosed magic call {}", call)))?;
+
+    let mut args = Vec::new();
+    for pair in body.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (k, v) = pair.split_once('=')
+            .ok_or_else(|| XError::MagicError(format!("invalid argument {} in {}", pair, call)))?;
+        let (k, v) = (k.trim(), v.trim());
+        if !is_safe_value(v) {
+            return Err(XError::MagicError(format!("invalid value {} for {}", v, k)));
+        }
+        if args.iter().any(|(name, _)| *name == k) {
+            return Err(XError::MagicError(format!("duplicate argument {} in {}", k, call)));
+        }
+        args.push((k, v));
+    }
+    Ok((name.trim(), args))
+}
+
+// 参数值会被直接拼接进 URL，只允许安全字符
+fn is_safe_value(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+pub fn placeholders(template: &str) -> XResult<Vec<&str>> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}')
+            .ok_or_else(|| XError::MagicError(format!("unclosed placeholder in {}", template)))?;
+        names.push(&rest[start + 1..start + end]);
+        rest = &rest[start + end + 1..];
+    }
+    Ok(names)
+}
+
+// render_template 的逆过程：占位符只匹配安全字符，返回各占位符的取值
+pub fn match_template<'t, 's>(template: &'t str, text: &'s str) -> Option<Vec<(&'t str, &'s str)>> {
+    let Some(start) = template.find('{') else {
+        return (template == text).then(Vec::new);
+    };
+    let text = text.strip_prefix(&template[..start])?;
+    let end = start + template[start..].find('}')?;
+    let (name, rest) = (&template[start + 1..end], &template[end + 1..]);
+    // 先取最短的值，剩余模板匹配不上时再加长
+    let max = text.find(|c: char| !(c.is_ascii_alphanumeric() || "._-".contains(c))).unwrap_or(text.len());
+    (1..=max).find_map(|len| {
+        let mut values = match_template(rest, &text[len..])?;
+        values.insert(0, (name, &text[..len]));
+        Some(values)
+    })
+}
+
+pub fn render_template(template: &str, values: &HashMap<&str, &str>) -> XResult<String> {
+    let mut out = template.to_string();
+    for name in placeholders(template)? {
+        let value = values.get(name)
+            .ok_or_else(|| XError::MagicError(format!("missing parameter {}", name)))?;
+        out = out.replace(&format!("{{{}}}", name), value);
+    }
+    Ok(out)
+}
+
+impl CloudMagic {
+    // 加载配置时检查模板：声明了 params 时占位符必须都已声明，默认值必须是安全字符
+    pub fn validate(&self) -> XResult<()> {
+        for param in &self.params {
+            if let Some(default) = &param.default {
+                if !is_safe_value(default) {
+                    return Err(XError::MagicError(format!("invalid default {} for {}.{}", default, self.name, param.name)));
+                }
+            }
+        }
+        for template in self.cloud_cfg.values() {
+            for name in placeholders(template)? {
+                if !self.params.is_empty() && !self.params.iter().any(|p| p.name == name) {
+                    return Err(XError::MagicError(format!("undeclared parameter {} in {}", name, self.name)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 校验参数并渲染每个云的模板
+    pub fn render(&self, args: &[(&str, &str)]) -> XResult<CloudMagic> {
+        let mut values: HashMap<&str, &str> = HashMap::new();
+
+        if self.params.is_empty() {
+            for (k, v) in args {
+                values.insert(k, v);
+            }
+        } else {
+            for (k, _) in args {
+                if !self.params.iter().any(|p| p.name == *k) {
+                    return Err(XError::MagicError(format!("unknown parameter {} for {}", k, self.name)));
+                }
+            }
+            for param in &self.params {
+                let value = args.iter()
+                    .find(|(k, _)| *k == param.name)
+                    .map(|(_, v)| *v)
+                    .or(param.default.as_deref());
+                match value {
+                    Some(value) => {
+                        values.insert(&param.name, value);
+                    }
+                    None => {
+                        return Err(XError::MagicError(format!("missing required parameter {} for {}", param.name, self.name)));
+                    }
+                }
+            }
+        }
+
+        let cloud_cfg = self.cloud_cfg.iter()
+            .map(|(cloud, template)| Ok((cloud.clone(), render_template(template, &values)?)))
+            .collect::<XResult<HashMap<_, _>>>()?;
+
+        Ok(CloudMagic {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            cloud_cfg,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MagicParam;
+
+    fn thumbnail() -> CloudMagic {
+        let mut cloud_cfg = HashMap::new();
+        cloud_cfg.insert("cos".to_string(), "imageMogr2/thumbnail/{w}x{h}>".to_string());
+        cloud_cfg.insert("oss".to_string(), "x-oss-process=image/resize,w_{w},h_{h}".to_string());
+        CloudMagic {
+            name: "thumbnail".to_string(),
+            params: vec![
+                MagicParam { name: "w".to_string(), default: None },
+                MagicParam { name: "h".to_string(), default: Some("200".to_string()) },
+            ],
+            cloud_cfg,
+        }
+    }
+
+    #[test]
+    fn test_parse_call() {
+        assert_eq!(parse_call("thumbnail_200").unwrap(), ("thumbnail_200", vec![]));
+        assert_eq!(parse_call("thumbnail(w=320, h=240)").unwrap(), ("thumbnail", vec![("w", "320"), ("h", "240")]));
+        assert!(parse_call("thumbnail(w=320").is_err());
+        assert!(parse_call("thumbnail(w=3&x=1)").is_err());
+        assert!(parse_call("thumbnail(w=1,w=2)").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let magic = thumbnail().render(&[("w", "320"), ("h", "240")]).unwrap();
+        assert_eq!(magic.get_magic("cos"), Some("imageMogr2/thumbnail/320x240>"));
+        assert_eq!(magic.get_magic("oss"), Some("x-oss-process=image/resize,w_320,h_240"));
+
+        let magic = thumbnail().render(&[("w", "320")]).unwrap();
+        assert_eq!(magic.get_magic("cos"), Some("imageMogr2/thumbnail/320x200>"));
+
+        assert!(thumbnail().render(&[("h", "240")]).is_err());
+        assert!(thumbnail().render(&[("w", "1"), ("q", "80")]).is_err());
+    }
+
+    #[test]
+    fn test_match_template() {
+        assert_eq!(match_template("imageMogr2/thumbnail/{w}x{h}>", "imageMogr2/thumbnail/320x240>"), Some(vec![("w", "320"), ("h", "240")]));
+        assert_eq!(match_template("image/resize,w_{w},h_{h}", "image/resize,w_3.5,h_a-b"), Some(vec![("w", "3.5"), ("h", "a-b")]));
+        assert_eq!(match_template("format/jpg", "format/jpg"), Some(vec![]));
+        assert_eq!(match_template("imageMogr2/thumbnail/{w}x{h}>", "imageMogr2/thumbnail/x240>"), None);
+        assert_eq!(match_template("w_{w}", "w_1/2"), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(thumbnail().validate().is_ok());
+
+        let mut magic = thumbnail();
+        magic.cloud_cfg.insert("tos".to_string(), "image/resize,w_{w},q_{q}".to_string());
+        assert!(matches!(magic.validate(), Err(XError::MagicError(_))));
+
+        let mut magic = thumbnail();
+        magic.params[1].default = Some("2&x=1".to_string());
+        assert!(magic.validate().is_err());
+
+        let mut magic = thumbnail();
+        magic.cloud_cfg.insert("tos".to_string(), "image/resize,w_{w".to_string());
+        assert!(magic.validate().is_err());
+    }
+}
@@ -1,71 +1,166 @@
+pub mod compose;
+pub mod magic;
+pub mod parse;
+pub mod process;
+pub mod sign;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-use crate::config::{BucketSource, CloudMagic};
+use serde_json::Value;
+use crate::config::{BucketSource, CloudMagic, Config};
+use crate::error::{XError, XResult};
+use crate::strategy::Credentials;
+use crate::xkey::XKey;
 
-pub fn resolve(
-    branch_cloud_source: &HashMap<String, BucketSource>,
-    key: &str,
-    magics: &[&CloudMagic]
-) -> String {
-    if !key.chars().all(|c| c.is_ascii()) {
-        return key.to_string();
+fn collect_queries(bucket_source: Option<&BucketSource>, query_part: Option<&str>, magics: &[CloudMagic]) -> XResult<Vec<String>> {
+    let mut queries = Vec::new();
+
+    if let Some(query_part) = query_part {
+        queries.push(query_part.to_string());
     }
 
-    if key.starts_with("http") || key.starts_with("wxfile") {
-        return key.to_string();
+    // 处理魔法参数
+    let cloud = bucket_source.and_then(|bucket_source| bucket_source.cloud.as_deref());
+    if let Some(cloud) = cloud {
+        for magic in magics {
+            if let Some(q) = magic.get_magic(cloud).filter(|q| !q.is_empty()) {
+                queries.push(q.to_string());
+            }
+        }
     }
 
-    let key = if key.starts_with('/') { &key[1..] } else { key };
+    compose::compose(cloud.unwrap_or(""), &queries)
+}
 
-    let (key_part, query_part) = key.split_once('?').unwrap_or((key, ""));
+#[derive(Default, Clone, Copy)]
+pub struct ResolveOptions<'a> {
+    // 用于解析 bucket 的 fallback 链
+    pub config: Option<&'a Config>,
+    // 跳过被标记为不可用的域名
+    pub unhealthy_domains: Option<&'a HashSet<String>>,
+    // 只使用这些云的域名，如图片处理只能交给支持它的云
+    pub clouds: Option<&'a [&'a str]>,
+}
 
-    let (cloud_name, _) = if key.starts_with('_') {
-        let (cloud_name, key_rest) = key_part.split_once('/').unwrap_or((key_part, ""));
-        (cloud_name, key_rest)
-    } else {
-        // 处理旧格式的 key
-        let mut cloud_name = "_cos";
-        if key_part.contains("cos") {
-            cloud_name = "_cos";
-        } else if key_part.contains("tos") {
-            cloud_name = "_tos";
-        } else if key_part.contains("oss") {
-            cloud_name = "_oss";
+// 依次列出 cdnDomain、domain，再沿 fallback 链收集可用的域名
+fn candidate_domains<'a>(bucket_source: &'a BucketSource, opts: &ResolveOptions<'a>) -> Vec<(&'a BucketSource, &'a str)> {
+    let is_healthy = |domain: &str| opts.unhealthy_domains.is_none_or(|set| !set.contains(domain));
+    let is_allowed = |source: &BucketSource| opts.clouds.is_none_or(|clouds| {
+        source.cloud.as_deref().is_some_and(|cloud| clouds.contains(&cloud))
+    });
+    let mut visited: Vec<&BucketSource> = Vec::new();
+    let mut candidates = Vec::new();
+    let mut current = bucket_source;
+
+    loop {
+        if is_allowed(current) {
+            candidates.extend([&current.cdn_domain, &current.domain].into_iter()
+                .flatten()
+                .filter(|domain| !domain.is_empty() && is_healthy(domain))
+                .map(|domain| (current, domain.as_str())));
         }
-        (cloud_name, key_part)
-    };
 
-    let mut queries = Vec::new();
+        visited.push(current);
+        match opts.config.and_then(|config| config.fallback_bucket(current)) {
+            Some(next) if !visited.contains(&next) => current = next,
+            _ => return candidates,
+        }
+    }
+}
 
-    if !query_part.is_empty() {
-        queries.push(query_part.to_string());
+fn pick_domain<'a>(bucket_source: &'a BucketSource, opts: &ResolveOptions<'a>) -> Option<(&'a BucketSource, &'a str)> {
+    candidate_domains(bucket_source, opts).into_iter().next()
+}
+
+// 按优先级列出对象的所有可访问地址，不带魔法参数
+pub fn resolve_candidates(
+    branch_cloud_source: &HashMap<String, Arc<BucketSource>>,
+    key: &str,
+    opts: &ResolveOptions,
+) -> XResult<Vec<String>> {
+    let xkey = XKey::parse(key)?;
+    let rules = opts.config.map(|config| config.legacy_rules.as_slice()).unwrap_or(&[]);
+    let bucket_source = branch_cloud_source.get(xkey.cloud_name(rules))
+        .ok_or_else(|| XError::NoAvailableDomain(key.to_string()))?;
+    Ok(candidate_domains(bucket_source, opts).into_iter()
+        .map(|(source, domain)| format!("{}/{}", source.base_url(domain), xkey.object_key()))
+        .collect())
+}
+
+pub fn resolve(
+    branch_cloud_source: &HashMap<String, Arc<BucketSource>>,
+    key: &str,
+    magics: &[CloudMagic],
+    opts: &ResolveOptions,
+) -> XResult<String> {
+    if !key.is_ascii() {
+        return Ok(key.to_string());
     }
 
-    // 处理魔法参数
-    if let Some(bucket_source) = branch_cloud_source.get(cloud_name) {
-      let cloud = bucket_source.cloud.as_ref();
-      if let Some(cloud) = cloud {
-        for magic in magics {
-          if let Some(q) = magic.get_magic(cloud) {
-            queries.push(q.to_string());
-          }
-        }
-      }
+    if key.starts_with("http") || key.starts_with("wxfile") {
+        return Ok(key.to_string());
     }
 
-    let base_url = format!(
-        "https://{}",
-        &branch_cloud_source[cloud_name].cdn_domain.as_ref().unwrap_or(&"".to_string())
-    );
+    let xkey = XKey::parse(key)?;
+    let rules = opts.config.map(|config| config.legacy_rules.as_slice()).unwrap_or(&[]);
+    let cloud_name = xkey.cloud_name(rules);
+
+    let bucket_source = branch_cloud_source.get(cloud_name)
+        .ok_or_else(|| XError::NoAvailableDomain(key.to_string()))?;
+    let (bucket_source, domain) = pick_domain(bucket_source, opts)
+        .ok_or_else(|| XError::NoAvailableDomain(key.to_string()))?;
+
+    let queries = collect_queries(Some(bucket_source), xkey.query(), magics)?;
+
+    let base_url = bucket_source.base_url(domain);
 
     if queries.is_empty() {
-        format!("{}/{}", base_url, key_part)
+        Ok(format!("{}/{}", base_url, xkey.object_key()))
     } else {
-        format!("{}/{}?{}", base_url, key_part, queries.join("&"))
+        Ok(format!("{}/{}?{}", base_url, xkey.object_key(), queries.join("&")))
     }
 }
 
+// 生成带签名的私有访问地址，location 为 Strategy::domain_parser 的解析结果
+pub fn resolve_signed(
+    bucket_source: &BucketSource,
+    key: &str,
+    magics: &[CloudMagic],
+    credentials: &Credentials,
+    location: &Value,
+    expires: i64,
+) -> XResult<String> {
+    let xkey = XKey::parse(key)?;
+    let queries = collect_queries(Some(bucket_source), xkey.query(), magics)?;
+    let query = sign::parse_query(&queries.join("&"));
+    let path = format!("/{}", xkey.object_key());
+    let now = chrono::Utc::now().timestamp();
+
+    if let (Some(cdn_domain), Some(cdn_auth)) = (&bucket_source.cdn_domain, &bucket_source.cdn_auth) {
+        let auth_key = credentials.cdn_auth_key.as_deref()
+            .ok_or_else(|| XError::SignFailed("missing cdn auth key".to_string()))?;
+        return sign::cdn_presign(cdn_auth, auth_key, cdn_domain, &path, &query, now);
+    }
+
+    let domain = bucket_source.domain.as_deref().ok_or(XError::InvalidConfig)?;
+    let cloud = bucket_source.cloud.as_deref().ok_or(XError::InvalidConfig)?;
+    let bucket = location["bucket"].as_str()
+        .unwrap_or_else(|| domain.split('.').next().unwrap_or(""));
+
+    sign::presign(cloud, &sign::SignRequest {
+        method: "GET",
+        host: domain,
+        bucket,
+        region: location["region"].as_str().unwrap_or(""),
+        path: &path,
+        query,
+        headers: vec![],
+        now,
+        expires,
+    }, credentials)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,7 +171,7 @@ mod tests {
         use crate::config::{BucketSource, CloudMagic};
 
         let mut bucket_cloud_source = HashMap::new();
-        let mut bucket_source = BucketSource {
+        let bucket_source = BucketSource {
             name: "_cos".to_string(),
             cloud: Some("cos".to_string()),
             cdn_domain: Some("example.cos.com".to_string()),
@@ -84,22 +179,157 @@ mod tests {
             fallback: None,
             cloud_name: None,
             grayscale: None,
+            cdn_auth: None,
+            key_template: None,
+            container: None,
         };
-        bucket_cloud_source.insert("_cos".to_string(), bucket_source);
+        bucket_cloud_source.insert("_cos".to_string(), Arc::new(bucket_source));
 
         let mut cloud_cfg = HashMap::new();
         cloud_cfg.insert("cos".to_string(), "imageMogr2/thumbnail/200".to_string());
         let magic = CloudMagic {
             name: "thumbnail".to_string(),
+            params: vec![],
             cloud_cfg,
         };
 
         let key = "_cos/test.jpg";
 
-        let url = resolve(&bucket_cloud_source, key, &[&magic]);
+        let url = resolve(&bucket_cloud_source, key, &[magic], &ResolveOptions::default()).unwrap();
         assert_eq!(
             url,
             "https://example.cos.com/_cos/test.jpg?imageMogr2/thumbnail/200"
         );
     }
+
+    #[test]
+    fn test_resolve_signed() {
+        use crate::config::CdnAuth;
+
+        let bucket_source = BucketSource {
+            name: "disk".to_string(),
+            cloud: Some("oss".to_string()),
+            cdn_domain: Some("disk.example.com".to_string()),
+            domain: Some("disk-bucket.oss-cn-hangzhou.aliyuncs.com".to_string()),
+            fallback: None,
+            cloud_name: Some("_oss".to_string()),
+            grayscale: None,
+            cdn_auth: None,
+            key_template: None,
+            container: None,
+        };
+        let mut cloud_cfg = HashMap::new();
+        cloud_cfg.insert("oss".to_string(), "x-oss-process=image/resize,w_200".to_string());
+        let magic = CloudMagic {
+            name: "thumbnail".to_string(),
+            params: vec![],
+            cloud_cfg,
+        };
+        let credentials = Credentials {
+            secret_id: "ak".to_string(),
+            secret_key: "sk".to_string(),
+            session_token: Some("token".to_string()),
+            cdn_auth_key: Some("cdnkey".to_string()),
+        };
+        let location = serde_json::json!({ "bucket": "disk-bucket", "region": "cn-hangzhou" });
+
+        let url = resolve_signed(&bucket_source, "_oss/a.jpg", std::slice::from_ref(&magic), &credentials, &location, 600).unwrap();
+        assert!(url.starts_with("https://disk-bucket.oss-cn-hangzhou.aliyuncs.com/_oss/a.jpg?OSSAccessKeyId=ak&Expires="));
+        assert!(url.contains("&x-oss-process=image/resize,w_200"));
+        assert!(url.contains("&security-token=token"));
+
+        let bucket_source = BucketSource {
+            cdn_auth: Some(CdnAuth { kind: "A".to_string(), param: Some("auth_key".to_string()) }),
+            ..bucket_source
+        };
+        let url = resolve_signed(&bucket_source, "_oss/a.jpg", &[magic], &credentials, &location, 600).unwrap();
+        assert!(url.starts_with("https://disk.example.com/_oss/a.jpg?auth_key="));
+        assert!(url.ends_with("&x-oss-process=image/resize,w_200"));
+    }
+
+    #[test]
+    fn test_resolve_fallback() {
+        let config = Config::from_json(serde_json::json!({
+            "cloudSource": [{
+                "name": "_tos",
+                "cloud": "tos",
+                "buckets": [
+                    { "name": "img", "fallback": "_cos" },
+                    { "name": "video", "domain": "video.tos.example.com", "fallback": "_cos" }
+                ]
+            }, {
+                "name": "_cos",
+                "cloud": "cos",
+                "buckets": [
+                    { "name": "img", "domain": "img-bucket.cos.example.com", "cdnDomain": "img.example.com" },
+                    { "name": "video", "domain": "video-bucket.cos.example.com" }
+                ]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        let branch = |bucket: &str| config.cloud_source.iter()
+            .filter_map(|source| source.buckets.iter()
+                .find(|b| b.name == bucket)
+                .map(|b| (source.name.clone(), Arc::new(b.clone()))))
+            .collect::<HashMap<_, _>>();
+        let mut cloud_cfg = HashMap::new();
+        cloud_cfg.insert("cos".to_string(), "imageMogr2/thumbnail/200x".to_string());
+        let magics = [CloudMagic { name: "thumbnail".to_string(), params: vec![], cloud_cfg }];
+
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: None, clouds: None };
+        // 没有任何域名时沿 fallback 链切换到 _cos，并按 cos 处理魔法参数
+        let url = resolve(&branch("img"), "_tos/a.jpg", &magics, &opts).unwrap();
+        assert_eq!(url, "https://img.example.com/_tos/a.jpg?imageMogr2/thumbnail/200x");
+
+        // 没有 cdnDomain 时使用源站域名
+        let url = resolve(&branch("video"), "_tos/a.mp4", &[], &opts).unwrap();
+        assert_eq!(url, "https://video.tos.example.com/_tos/a.mp4");
+
+        let unhealthy = HashSet::from(["video.tos.example.com".to_string()]);
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: Some(&unhealthy), clouds: None };
+        let url = resolve(&branch("video"), "_tos/a.mp4", &[], &opts).unwrap();
+        assert_eq!(url, "https://video-bucket.cos.example.com/_tos/a.mp4");
+
+        assert!(matches!(resolve(&branch("video"), "_oss/a.mp4", &[], &opts), Err(XError::NoAvailableDomain(_))));
+
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: None, clouds: None };
+        assert_eq!(resolve_candidates(&branch("video"), "_tos/a.mp4?x", &opts).unwrap(), vec![
+            "https://video.tos.example.com/_tos/a.mp4",
+            "https://video-bucket.cos.example.com/_tos/a.mp4",
+        ]);
+        assert!(matches!(resolve(&branch("img"), "_tos/a.jpg", &[], &ResolveOptions::default()), Err(XError::NoAvailableDomain(_))));
+
+        // 限定云时跳过其他云的域名
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: None, clouds: Some(&["cos"]) };
+        let url = resolve(&branch("video"), "_tos/a.mp4", &[], &opts).unwrap();
+        assert_eq!(url, "https://video-bucket.cos.example.com/_tos/a.mp4");
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: None, clouds: Some(&["oss"]) };
+        assert!(matches!(resolve(&branch("video"), "_tos/a.mp4", &[], &opts), Err(XError::NoAvailableDomain(_))));
+    }
+
+    #[test]
+    fn test_resolve_container() {
+        let config = Config::from_json(serde_json::json!({
+            "cloudSource": [{
+                "name": "_azure",
+                "cloud": "azure",
+                "buckets": [{
+                    "name": "img",
+                    "container": "photos",
+                    "domain": "acme.blob.core.windows.net",
+                    "cdnDomain": "acme.azureedge.net"
+                }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        let branch = HashMap::from([("_azure".to_string(), Arc::new(config.cloud_source[0].buckets[0].clone()))]);
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: None, clouds: None };
+
+        let url = resolve(&branch, "_azure/a.jpg", &[], &opts).unwrap();
+        assert_eq!(url, "https://acme.azureedge.net/photos/_azure/a.jpg");
+        assert_eq!(resolve_candidates(&branch, "_azure/a.jpg", &opts).unwrap(), vec![
+            "https://acme.azureedge.net/photos/_azure/a.jpg",
+            "https://acme.blob.core.windows.net/photos/_azure/a.jpg",
+        ]);
+    }
 }
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use crate::config::{CloudMagic, Config};
+use crate::error::{XError, XResult};
+use crate::xkey::XKey;
+use super::magic::match_template;
+
+// 签名 URL 中的鉴权参数，不属于 key 或魔法参数
+const SIGN_PARAMS: [&str; 7] = [
+    "x-cos-security-token", "OSSAccessKeyId", "Expires", "Signature",
+    "security-token", "sign", "auth_key",
+];
+
+fn is_sign_param(name: &str) -> bool {
+    name.starts_with("q-") || name.starts_with("X-Tos-") || SIGN_PARAMS.contains(&name)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParsedUrl {
+    pub cloud_name: String,
+    pub cloud: Option<String>,
+    pub bucket: String,
+    pub key: String,
+    // 命中配置中的魔法参数，带参数的写成 thumbnail(w=320,h=240)，可直接传回 resolve
+    pub magics: Vec<String>,
+    // 未识别的查询参数，原样保留
+    pub query: Vec<String>,
+}
+
+pub fn parse_url(config: &Config, cloud_magics: &HashMap<String, CloudMagic>, url: &str) -> XResult<ParsedUrl> {
+    let rest = url.split_once("://").map(|(_, rest)| rest)
+        .or_else(|| url.strip_prefix("//"))
+        .ok_or_else(|| XError::InvalidUrl(url.to_string()))?;
+    let (host_path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let (host, path) = host_path.split_once('/').unwrap_or((host_path, ""));
+    let host = host.split(':').next().unwrap_or(host);
+    let path = percent_decode_str(path).decode_utf8_lossy().to_string();
+
+    // 同一个域名可能挂在多个 bucket 上，优先选 key 前缀对应的云源；
+    // 域名或 container 带路径时，去掉这部分路径才是 key
+    let candidates: Vec<_> = config.cloud_source.iter()
+        .flat_map(|source| source.buckets.iter().map(move |bucket| (source, bucket)))
+        .filter_map(|(source, bucket)| {
+            let prefix = [&bucket.cdn_domain, &bucket.domain].into_iter()
+                .flatten()
+                .filter_map(|domain| domain_prefix(domain, host))
+                .next()?;
+            let key = match prefix {
+                "" => path.as_str(),
+                prefix => strip_dir(&path, prefix)?,
+            };
+            let key = match &bucket.container {
+                Some(container) => strip_dir(key, container)?,
+                None => key,
+            };
+            Some((source, bucket, key.to_string()))
+        })
+        .collect();
+    let (source, bucket, key) = candidates.iter()
+        .find(|(source, _, key)| XKey::parse(key).is_ok_and(|xkey| xkey.cloud() == Some(source.name.as_str())))
+        .or_else(|| candidates.first())
+        .cloned()
+        .ok_or_else(|| XError::InvalidUrl(url.to_string()))?;
+    let cloud = bucket.cloud.clone().or_else(|| source.cloud.clone());
+
+    let mut params: Vec<&str> = query.split('&')
+        .filter(|p| !p.is_empty())
+        .filter(|p| !is_sign_param(p.split('=').next().unwrap_or("")))
+        .collect();
+
+    let mut magics = Vec::new();
+    if let Some(cloud) = cloud.as_deref() {
+        let mut candidates: Vec<(&CloudMagic, Vec<&str>)> = cloud_magics.values()
+            .filter_map(|magic| magic.get_magic(cloud).filter(|q| !q.is_empty()).map(|q| (magic, q.split('&').collect())))
+            .collect();
+        // 优先匹配查询参数更多的，其次是不带占位符的，名称排序保证结果稳定
+        candidates.sort_by_key(|(magic, parts)| {
+            (std::cmp::Reverse(parts.len()), parts.iter().any(|part| part.contains('{')), magic.name.clone())
+        });
+        for (magic, parts) in candidates {
+            if let Some((matched, values)) = match_magic(&parts, &params) {
+                params.retain(|p| !matched.contains(p));
+                magics.push(magic_call(magic, &values));
+            }
+        }
+    }
+
+    Ok(ParsedUrl {
+        cloud_name: source.name.clone(),
+        cloud,
+        bucket: bucket.name.clone(),
+        key,
+        magics,
+        query: params.into_iter().map(|p| p.to_string()).collect(),
+    })
+}
+
+// 命中的查询参数和占位符取值
+type MagicMatch<'t, 'q> = (Vec<&'q str>, Vec<(&'t str, &'q str)>);
+
+// 模板的每一段都要命中一个查询参数，同名占位符的取值必须一致
+fn match_magic<'t, 'q>(parts: &[&'t str], params: &[&'q str]) -> Option<MagicMatch<'t, 'q>> {
+    let mut matched = Vec::new();
+    let mut values: Vec<(&str, &str)> = Vec::new();
+    for part in parts {
+        let (param, captured) = params.iter()
+            .filter(|param| !matched.contains(*param))
+            .find_map(|param| match_template(part, param).map(|captured| (*param, captured)))?;
+        for (name, value) in captured {
+            match values.iter().find(|(n, _)| *n == name) {
+                Some((_, v)) if *v != value => return None,
+                Some(_) => {}
+                None => values.push((name, value)),
+            }
+        }
+        matched.push(param);
+    }
+    Some((matched, values))
+}
+
+// 按声明顺序写出参数，没有声明 params 时按模板中出现的顺序
+fn magic_call(magic: &CloudMagic, values: &[(&str, &str)]) -> String {
+    if values.is_empty() {
+        return magic.name.clone();
+    }
+    let order: Vec<&str> = if magic.params.is_empty() {
+        values.iter().map(|(name, _)| *name).collect()
+    } else {
+        magic.params.iter().map(|p| p.name.as_str()).collect()
+    };
+    let args: Vec<String> = order.iter()
+        .filter_map(|name| values.iter().find(|(n, _)| n == name).map(|(n, v)| format!("{}={}", n, v)))
+        .collect();
+    format!("{}({})", magic.name, args.join(","))
+}
+
+// 域名与 host 匹配时返回域名中的路径部分
+fn domain_prefix<'a>(domain: &'a str, host: &str) -> Option<&'a str> {
+    let domain = domain.split_once("://").map(|(_, rest)| rest).unwrap_or(domain);
+    let (domain_host, prefix) = domain.split_once('/').unwrap_or((domain, ""));
+    (domain_host.split(':').next() == Some(host)).then(|| prefix.trim_matches('/'))
+}
+
+fn strip_dir<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    path.strip_prefix(dir)?.strip_prefix('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let config = Config::from_json(serde_json::json!({
+            "cloudSource": [{
+                "name": "_cos",
+                "cloud": "cos",
+                "buckets": [
+                    { "name": "backup-img", "domain": "img.example.com", "cdnDomain": "img.example.com" },
+                    { "name": "img", "domain": "img-bucket.cos.example.com", "cdnDomain": "img.example.com" }
+                ]
+            }, {
+                "name": "_tos",
+                "cloud": "tos",
+                "buckets": [{ "name": "img", "domain": "img.tos.example.com" }]
+            }],
+            "cloudMagics": [{
+                "name": "thumbnail_200",
+                "cloudCfg": { "cos": "imageMogr2/thumbnail/200>/format/jpg" }
+            }, {
+                "name": "video_cover",
+                "cloudCfg": { "cos": "ci-process=snapshot&time=1.5" }
+            }, {
+                "name": "thumbnail",
+                "params": [{ "name": "w" }, { "name": "h", "default": "200" }],
+                "cloudCfg": { "cos": "imageMogr2/thumbnail/{w}x{h}>&quality={w}" }
+            }]
+        })).unwrap();
+        let magics: HashMap<String, CloudMagic> = config.cloud_magics.iter()
+            .map(|m| (m.name.clone(), m.clone()))
+            .collect();
+
+        let parsed = parse_url(&config, &magics, "https://img.example.com/_cos/abc.jpg?imageMogr2/thumbnail/200>/format/jpg&x=1").unwrap();
+        assert_eq!(parsed.cloud_name, "_cos");
+        assert_eq!(parsed.bucket, "backup-img");
+        assert_eq!(parsed.key, "_cos/abc.jpg");
+        assert_eq!(parsed.magics, vec!["thumbnail_200"]);
+        assert_eq!(parsed.query, vec!["x=1"]);
+
+        let parsed = parse_url(&config, &magics, "https://img-bucket.cos.example.com/_cos/a%20b.mp4?q-sign-algorithm=sha1&q-ak=x&ci-process=snapshot&time=1.5").unwrap();
+        assert_eq!(parsed.bucket, "img");
+        assert_eq!(parsed.key, "_cos/a b.mp4");
+        assert_eq!(parsed.magics, vec!["video_cover"]);
+        assert!(parsed.query.is_empty());
+
+        // 带参数的魔法参数取回参数值
+        let parsed = parse_url(&config, &magics, "https://img.example.com/_cos/a.jpg?quality=320&imageMogr2/thumbnail/320x240>").unwrap();
+        assert_eq!(parsed.magics, vec!["thumbnail(w=320,h=240)"]);
+        assert!(parsed.query.is_empty());
+        let parsed = parse_url(&config, &magics, "https://img.example.com/_cos/a.jpg?imageMogr2/thumbnail/320x240>&quality=80").unwrap();
+        assert!(parsed.magics.is_empty());
+
+        let parsed = parse_url(&config, &magics, "http://img.tos.example.com/legacy/a.png").unwrap();
+        assert_eq!(parsed.cloud_name, "_tos");
+        assert_eq!(parsed.key, "legacy/a.png");
+
+        assert!(parse_url(&config, &magics, "https://unknown.example.com/a.png").is_err());
+        assert!(parse_url(&config, &magics, "wxfile://tmp/a.png").is_err());
+    }
+
+    #[test]
+    fn test_parse_container_url() {
+        let config = Config::from_json(serde_json::json!({
+            "cloudSource": [{
+                "name": "_azure",
+                "cloud": "azure",
+                "buckets": [
+                    { "name": "img", "container": "photos", "domain": "acme.blob.core.windows.net" },
+                    { "name": "video", "container": "videos", "domain": "acme.blob.core.windows.net" }
+                ]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+
+        let parsed = parse_url(&config, &HashMap::new(), "https://acme.blob.core.windows.net/videos/_azure/a.mp4").unwrap();
+        assert_eq!(parsed.bucket, "video");
+        assert_eq!(parsed.key, "_azure/a.mp4");
+        assert!(parse_url(&config, &HashMap::new(), "https://acme.blob.core.windows.net/other/_azure/a.mp4").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use base64::Engine;
+use crate::config::CloudMagic;
+use crate::error::{XError, XResult};
+
+const SUPPORTED_CLOUDS: [&str; 4] = ["cos", "oss", "tos", "qiniu"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    // 等比缩放，限制在宽高范围内
+    Fit,
+    // 等比缩放后居中裁剪，填满宽高
+    Fill,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpg,
+    Png,
+    Webp,
+    Gif,
+}
+
+impl ImageFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Gif => "gif",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Resize { width: Option<u32>, height: Option<u32>, mode: ResizeMode },
+    Crop { x: u32, y: u32, width: u32, height: u32 },
+    Rotate(u32),
+    Format(ImageFormat),
+    Quality(u8),
+    Watermark(String),
+    Blur { radius: u32, sigma: u32 },
+    VideoSnapshot { time_ms: u64, width: Option<u32>, height: Option<u32> },
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Resize { .. } => "resize",
+            Operation::Crop { .. } => "crop",
+            Operation::Rotate(_) => "rotate",
+            Operation::Format(_) => "format",
+            Operation::Quality(_) => "quality",
+            Operation::Watermark(_) => "watermark",
+            Operation::Blur { .. } => "blur",
+            Operation::VideoSnapshot { .. } => "video_snapshot",
+        }
+    }
+}
+
+// 与云厂商无关的图片处理描述，按 cloud 类型编译成对应的处理参数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Process {
+    ops: Vec<Operation>,
+}
+
+impl Process {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ops(&self) -> &[Operation] {
+        &self.ops
+    }
+
+    pub fn push(mut self, op: Operation) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn resize(self, width: Option<u32>, height: Option<u32>, mode: ResizeMode) -> Self {
+        self.push(Operation::Resize { width, height, mode })
+    }
+
+    pub fn crop(self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.push(Operation::Crop { x, y, width, height })
+    }
+
+    pub fn rotate(self, degree: u32) -> Self {
+        self.push(Operation::Rotate(degree % 360))
+    }
+
+    pub fn format(self, format: ImageFormat) -> Self {
+        self.push(Operation::Format(format))
+    }
+
+    pub fn quality(self, quality: u8) -> Self {
+        self.push(Operation::Quality(quality.clamp(1, 100)))
+    }
+
+    pub fn watermark(self, text: &str) -> Self {
+        self.push(Operation::Watermark(text.to_string()))
+    }
+
+    pub fn blur(self, radius: u32, sigma: u32) -> Self {
+        self.push(Operation::Blur { radius, sigma })
+    }
+
+    pub fn video_snapshot(self, time_ms: u64, width: Option<u32>, height: Option<u32>) -> Self {
+        self.push(Operation::VideoSnapshot { time_ms, width, height })
+    }
+
+    pub fn render(&self, cloud: &str) -> XResult<String> {
+        if self.ops.is_empty() {
+            return Ok(String::new());
+        }
+        let is_video = self.ops.iter().any(|op| matches!(op, Operation::VideoSnapshot { .. }));
+        if is_video && self.ops.len() > 1 {
+            return Err(XError::UnsupportedOperation {
+                cloud: cloud.to_string(),
+                op: "video_snapshot with image operations".to_string(),
+            });
+        }
+
+        match cloud {
+            "cos" | "qiniu" => self.render_mogr(cloud),
+            "oss" => self.render_process("x-oss-process"),
+            "tos" => self.render_process("x-tos-process"),
+            _ => Err(self.unsupported(cloud, &self.ops[0])),
+        }
+    }
+
+    // 能渲染全部操作的云，resolve 时只在这些云的域名中选择
+    pub fn supported_clouds(&self) -> Vec<&'static str> {
+        SUPPORTED_CLOUDS.into_iter().filter(|cloud| self.render(cloud).is_ok()).collect()
+    }
+
+    // 渲染成一个临时 magic，便于和配置中的 magic 一起交给 resolver；
+    // fallback 到其他云时按对应云的语法生成
+    pub fn to_magic(&self) -> CloudMagic {
+        let cloud_cfg = SUPPORTED_CLOUDS.iter()
+            .filter_map(|cloud| self.render(cloud).ok().map(|q| (cloud.to_string(), q)))
+            .collect::<HashMap<_, _>>();
+        CloudMagic {
+            name: "process".to_string(),
+            params: vec![],
+            cloud_cfg,
+        }
+    }
+
+    fn unsupported(&self, cloud: &str, op: &Operation) -> XError {
+        XError::UnsupportedOperation {
+            cloud: cloud.to_string(),
+            op: op.name().to_string(),
+        }
+    }
+
+    // https://cloud.tencent.com/document/product/436/44880
+    // https://developer.qiniu.com/dora/8255/the-zoom
+    // 两家的 imageMogr2 基本一致，裁剪和视频截帧语法不同
+    fn render_mogr(&self, cloud: &str) -> XResult<String> {
+        let qiniu = cloud == "qiniu";
+        let mut mogr = Vec::new();
+        let mut pipes = Vec::new();
+        for op in &self.ops {
+            match op {
+                Operation::Resize { width, height, mode } => {
+                    let size = size_expr(*width, *height);
+                    match mode {
+                        ResizeMode::Fit => mogr.push(format!("thumbnail/{}", size)),
+                        ResizeMode::Fill => {
+                            mogr.push(format!("thumbnail/!{}r", size));
+                            mogr.push(format!("gravity/{}/crop/{}", if qiniu { "Center" } else { "center" }, size));
+                        }
+                    }
+                }
+                Operation::Crop { x, y, width, height } => mogr.push(if qiniu {
+                    format!("crop/!{}x{}a{}a{}", width, height, x, y)
+                } else {
+                    format!("cut/{}x{}x{}x{}", width, height, x, y)
+                }),
+                Operation::Rotate(degree) => mogr.push(format!("rotate/{}", degree)),
+                Operation::Format(format) => mogr.push(format!("format/{}", format.as_str())),
+                Operation::Quality(quality) => mogr.push(format!("quality/{}", quality)),
+                Operation::Blur { radius, sigma } => mogr.push(format!("blur/{}x{}", radius, sigma)),
+                Operation::Watermark(text) => pipes.push(format!("watermark/2/text/{}",
+                    base64::engine::general_purpose::URL_SAFE.encode(text))),
+                Operation::VideoSnapshot { time_ms, width, height } => {
+                    let seconds = *time_ms as f64 / 1000.0;
+                    let mut q = if qiniu {
+                        format!("vframe/jpg/offset/{}", seconds)
+                    } else {
+                        format!("ci-process=snapshot&time={}", seconds)
+                    };
+                    if let Some(w) = width {
+                        q.push_str(&if qiniu { format!("/w/{}", w) } else { format!("&width={}", w) });
+                    }
+                    if let Some(h) = height {
+                        q.push_str(&if qiniu { format!("/h/{}", h) } else { format!("&height={}", h) });
+                    }
+                    return Ok(q);
+                }
+            }
+        }
+        let mut parts = Vec::new();
+        if !mogr.is_empty() {
+            parts.push(format!("imageMogr2/{}", mogr.join("/")));
+        }
+        parts.extend(pipes);
+        Ok(parts.join("|"))
+    }
+
+    // OSS 与 TOS 的图片处理参数格式一致
+    fn render_process(&self, param: &str) -> XResult<String> {
+        let mut steps = Vec::new();
+        for op in &self.ops {
+            let step = match op {
+                Operation::Resize { width, height, mode } => {
+                    let mut step = format!("resize,m_{}", if *mode == ResizeMode::Fit { "lfit" } else { "fill" });
+                    if let Some(w) = width {
+                        step.push_str(&format!(",w_{}", w));
+                    }
+                    if let Some(h) = height {
+                        step.push_str(&format!(",h_{}", h));
+                    }
+                    step
+                }
+                Operation::Crop { x, y, width, height } => format!("crop,x_{},y_{},w_{},h_{}", x, y, width, height),
+                Operation::Rotate(degree) => format!("rotate,{}", degree),
+                Operation::Format(format) => format!("format,{}", format.as_str()),
+                Operation::Quality(quality) => format!("quality,q_{}", quality),
+                Operation::Blur { radius, sigma } => format!("blur,r_{},s_{}", radius, sigma),
+                Operation::Watermark(text) => format!("watermark,text_{}",
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(text)),
+                Operation::VideoSnapshot { time_ms, width, height } => {
+                    let mut step = format!("video/snapshot,t_{},f_jpg", time_ms);
+                    if let Some(w) = width {
+                        step.push_str(&format!(",w_{}", w));
+                    }
+                    if let Some(h) = height {
+                        step.push_str(&format!(",h_{}", h));
+                    }
+                    return Ok(format!("{}={}", param, step));
+                }
+            };
+            steps.push(step);
+        }
+        Ok(format!("{}=image/{}", param, steps.join("/")))
+    }
+}
+
+fn size_expr(width: Option<u32>, height: Option<u32>) -> String {
+    format!("{}x{}",
+        width.map(|w| w.to_string()).unwrap_or_default(),
+        height.map(|h| h.to_string()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let process = Process::new()
+            .resize(Some(200), None, ResizeMode::Fit)
+            .rotate(90)
+            .format(ImageFormat::Webp)
+            .quality(80);
+
+        assert_eq!(process.render("cos").unwrap(), "imageMogr2/thumbnail/200x/rotate/90/format/webp/quality/80");
+        assert_eq!(process.render("oss").unwrap(), "x-oss-process=image/resize,m_lfit,w_200/rotate,90/format,webp/quality,q_80");
+        assert_eq!(process.render("tos").unwrap(), "x-tos-process=image/resize,m_lfit,w_200/rotate,90/format,webp/quality,q_80");
+
+        let process = Process::new().resize(Some(100), Some(100), ResizeMode::Fill).watermark("xclouder");
+        assert_eq!(process.render("cos").unwrap(),
+            "imageMogr2/thumbnail/!100x100r/gravity/center/crop/100x100|watermark/2/text/eGNsb3VkZXI=");
+
+        let process = Process::new().crop(10, 20, 100, 50).resize(Some(100), Some(100), ResizeMode::Fill);
+        assert_eq!(process.render("qiniu").unwrap(),
+            "imageMogr2/crop/!100x50a10a20/thumbnail/!100x100r/gravity/Center/crop/100x100");
+
+        let snapshot = Process::new().video_snapshot(1500, Some(200), Some(200));
+        assert_eq!(snapshot.render("qiniu").unwrap(), "vframe/jpg/offset/1.5/w/200/h/200");
+        assert_eq!(snapshot.render("cos").unwrap(), "ci-process=snapshot&time=1.5&width=200&height=200");
+        assert_eq!(snapshot.render("tos").unwrap(), "x-tos-process=video/snapshot,t_1500,f_jpg,w_200,h_200");
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let process = Process::new().rotate(90);
+        assert!(matches!(process.render("mock"), Err(XError::UnsupportedOperation { .. })));
+
+        let process = Process::new().video_snapshot(0, None, None).format(ImageFormat::Png);
+        assert!(matches!(process.render("oss"), Err(XError::UnsupportedOperation { .. })));
+    }
+}
@@ -0,0 +1,291 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use base64::Engine;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::Value;
+use crate::error::{XError, XResult};
+use crate::resolver::sign::encode_path;
+use crate::{config::BucketSource, cloud_client::UploadOpts, Native, RequestArgs, UploadPartArgs};
+use super::{fetch_sts, require_native, xml, Strategy, UrlRes};
+
+const API_VERSION: &str = "2021-08-06";
+// 超过该大小走 Put Block + Put Block List
+const BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+
+pub struct Azure {
+    native: Option<Arc<dyn Native>>,
+    block_size: u64,
+}
+
+impl Azure {
+    pub fn new() -> Self {
+        Self {
+            native: None,
+            block_size: BLOCK_SIZE,
+        }
+    }
+
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    // 服务端下发 { sasToken, endpoint?, expireAt }，endpoint 用于 Azurite 等本地服务
+    fn blob_url(&self, bucket_source: &BucketSource, sts: &Value, key: &str) -> XResult<String> {
+        let container = bucket_source.container.as_deref().ok_or(XError::InvalidConfig)?;
+        let endpoint = match sts["endpoint"].as_str() {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => format!("https://{}", bucket_source.domain.as_deref().ok_or(XError::InvalidConfig)?),
+        };
+        Ok(format!("{}/{}/{}", endpoint, container, encode_path(key)))
+    }
+
+    async fn put_blocks(&self, native: &dyn Native, blob_url: &str, sas: &str, opts: &UploadOpts, size: u64) -> XResult<()> {
+        let count = size.div_ceil(self.block_size);
+        let mut block_ids = Vec::new();
+        for index in 0..count {
+            // 同一个 blob 的 block id 长度必须一致
+            let block_id = base64::engine::general_purpose::STANDARD.encode(format!("{:08}", index));
+            let offset = index * self.block_size;
+            native.upload_part(UploadPartArgs {
+                method: "PUT".to_string(),
+                url: format!("{}?comp=block&blockid={}&{}", blob_url,
+                    utf8_percent_encode(&block_id, NON_ALPHANUMERIC), sas),
+                headers: vec![("x-ms-version".to_string(), API_VERSION.to_string())],
+                file_path: opts.file_path.clone(),
+                offset,
+                size: self.block_size.min(size - offset),
+                response_type: "text".to_string(),
+            }).await?;
+            block_ids.push(block_id);
+
+            if let Some(on_progress) = &opts.on_progress {
+                on_progress((offset + self.block_size).min(size) as f32 / size as f32);
+            }
+        }
+
+        let body = format!(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>{}</BlockList>"#,
+            block_ids.iter().map(|id| format!("<Latest>{}</Latest>", xml::escape(id))).collect::<String>());
+        native.request(RequestArgs {
+            method: "PUT".to_string(),
+            url: format!("{}?comp=blocklist&{}", blob_url, sas),
+            enable_cache: false,
+            timeout: 10000,
+            response_type: "text".to_string(),
+            headers: vec![
+                ("x-ms-version".to_string(), API_VERSION.to_string()),
+                ("Content-Type".to_string(), "application/xml".to_string()),
+            ],
+            body: Some(body),
+        }).await?;
+        Ok(())
+    }
+}
+
+impl Default for Azure {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Strategy for Azure {
+    fn name(&self) -> &str {
+        "azure"
+    }
+
+    fn load_native(&mut self, native: Arc<dyn Native>) {
+        self.native = Some(native);
+    }
+
+    fn storage_key(&self, bucket_source: &BucketSource) -> String {
+        format!("sts:{}:{}",
+            bucket_source.cloud_name.as_deref().unwrap_or(""),
+            bucket_source.name
+        )
+    }
+
+    fn domain_parser(&self, domain: &str) -> Value {
+        // {account}.blob.core.windows.net
+        match domain.split_once(".blob.") {
+            Some((account, _)) => serde_json::json!({ "account": account }),
+            None => serde_json::json!({}),
+        }
+    }
+
+    async fn get_sts(&self, bucket_source: &BucketSource) -> XResult<Value> {
+        fetch_sts(require_native(&self.native)?, &self.storage_key(bucket_source), bucket_source).await
+    }
+
+    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts) -> XResult<UrlRes> {
+        let native = require_native(&self.native)?;
+        let sas = sts["sasToken"].as_str()
+            .map(|sas| sas.trim_start_matches('?'))
+            .ok_or_else(|| XError::SignFailed("azure sas token missing".to_string()))?;
+        let blob_url = self.blob_url(bucket_source, &sts, &opts.key)?;
+        let size = std::fs::metadata(&opts.file_path)
+            .map_err(|e| XError::UploadFailed(e.to_string()))?
+            .len();
+
+        if size <= self.block_size {
+            native.upload_part(UploadPartArgs {
+                method: "PUT".to_string(),
+                url: format!("{}?{}", blob_url, sas),
+                headers: vec![
+                    ("x-ms-version".to_string(), API_VERSION.to_string()),
+                    ("x-ms-blob-type".to_string(), "BlockBlob".to_string()),
+                ],
+                file_path: opts.file_path.clone(),
+                offset: 0,
+                size,
+                response_type: "text".to_string(),
+            }).await?;
+            if let Some(on_progress) = &opts.on_progress {
+                on_progress(1.0);
+            }
+        } else {
+            self.put_blocks(native, &blob_url, sas, opts, size).await?;
+        }
+
+        let domain = bucket_source.domain.clone().unwrap_or_default();
+        Ok(UrlRes {
+            base_url: bucket_source.base_url(&domain),
+            key: opts.key.clone(),
+            domain,
+            bucket: bucket_source.name.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::sync::Mutex;
+    use crate::testing::{upload_fixture, ScriptedNative};
+
+    const ENDPOINT: &str = "http://127.0.0.1:10000/devstoreaccount1";
+
+    // 模拟 Azurite 的 Blob 接口：校验 SAS，暂存 block，提交 block list 时拼接成 blob
+    #[derive(Default)]
+    struct Azurite {
+        blobs: Mutex<HashMap<String, Vec<u8>>>,
+        blocks: Mutex<HashMap<String, Vec<u8>>>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Azurite {
+        fn split(url: &str) -> XResult<(String, HashMap<String, String>)> {
+            let (path, query) = url.strip_prefix(ENDPOINT)
+                .and_then(|rest| rest.split_once('?'))
+                .ok_or_else(|| XError::InvalidUrl(url.to_string()))?;
+            let query: HashMap<String, String> = query.split('&')
+                .filter_map(|p| p.split_once('='))
+                .map(|(k, v)| (k.to_string(), percent_encoding::percent_decode_str(v).decode_utf8_lossy().to_string()))
+                .collect();
+            if query.get("sig").map(String::as_str) != Some("secret") {
+                return Err(XError::RequestFailed("403 AuthenticationFailed".to_string()));
+            }
+            Ok((path.to_string(), query))
+        }
+
+        fn put(&self, args: &UploadPartArgs) -> XResult<Value> {
+            let (path, query) = Self::split(&args.url)?;
+            let mut file = std::fs::File::open(&args.file_path).unwrap();
+            file.seek(SeekFrom::Start(args.offset)).unwrap();
+            let mut data = vec![0u8; args.size as usize];
+            file.read_exact(&mut data).unwrap();
+
+            match query.get("comp").map(String::as_str) {
+                Some("block") => {
+                    self.calls.lock().unwrap().push(format!("block {}", args.offset));
+                    self.blocks.lock().unwrap().insert(format!("{}#{}", path, query["blockid"]), data);
+                }
+                _ => {
+                    assert!(args.headers.contains(&("x-ms-blob-type".to_string(), "BlockBlob".to_string())));
+                    self.calls.lock().unwrap().push("blob".to_string());
+                    self.blobs.lock().unwrap().insert(path, data);
+                }
+            }
+            Ok(Value::String(String::new()))
+        }
+
+        fn put_block_list(&self, args: &RequestArgs) -> XResult<Value> {
+            let (path, query) = Self::split(&args.url)?;
+            assert_eq!(query.get("comp").map(String::as_str), Some("blocklist"));
+            self.calls.lock().unwrap().push("blocklist".to_string());
+            let body = args.body.clone().unwrap_or_default();
+            let mut blocks = self.blocks.lock().unwrap();
+            let mut blob = Vec::new();
+            for id in xml::blocks(&body, "Latest") {
+                let block = blocks.remove(&format!("{}#{}", path, id))
+                    .ok_or_else(|| XError::RequestFailed(format!("400 InvalidBlockList {}", id)))?;
+                blob.extend(block);
+            }
+            self.blobs.lock().unwrap().insert(path, blob);
+            Ok(Value::String(String::new()))
+        }
+
+        fn native(self: &Arc<Self>) -> Arc<ScriptedNative> {
+            let native = ScriptedNative::new();
+            native.on_upload(|_| Err(XError::UploadFailed("azure has no form upload".to_string())));
+            let azurite = self.clone();
+            native.on_upload_part(move |args| azurite.put(args));
+            let azurite = self.clone();
+            native.on_request(move |args| azurite.put_block_list(args));
+            native
+        }
+    }
+
+    fn setup(name: &str, data: &[u8]) -> (BucketSource, UploadOpts) {
+        upload_fixture(name, data, serde_json::json!({
+            "name": "img",
+            "container": "photos",
+            "domain": "acme.blob.core.windows.net",
+            "cloud": "azure",
+            "cloudName": "_azure",
+        }), "_azure/a b.jpg")
+    }
+
+    fn sts(sig: &str) -> Value {
+        serde_json::json!({ "sasToken": format!("?sv=2021-08-06&sp=cw&sig={}", sig), "endpoint": ENDPOINT })
+    }
+
+    #[tokio::test]
+    async fn test_put_blob() {
+        let azurite = Arc::new(Azurite::default());
+        let mut azure = Azure::new();
+        azure.load_native(azurite.native());
+        let (bs, opts) = setup("blob", b"hello");
+
+        let res = azure.upload(&bs, sts("secret"), &opts).await.unwrap();
+        assert_eq!(res.to_string(), "https://acme.blob.core.windows.net/photos/_azure/a b.jpg");
+        assert_eq!(azurite.blobs.lock().unwrap()["/photos/_azure/a%20b.jpg"], b"hello");
+        assert!(azure.upload(&bs, sts("bad"), &opts).await.is_err());
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[tokio::test]
+    async fn test_put_block_list() {
+        let azurite = Arc::new(Azurite::default());
+        let mut azure = Azure::new().with_block_size(4);
+        azure.load_native(azurite.native());
+        let (bs, opts) = setup("blocks", b"0123456789");
+
+        azure.upload(&bs, sts("secret"), &opts).await.unwrap();
+        assert_eq!(*azurite.calls.lock().unwrap(), vec!["block 0", "block 4", "block 8", "blocklist"]);
+        assert_eq!(azurite.blobs.lock().unwrap()["/photos/_azure/a%20b.jpg"], b"0123456789");
+        assert!(azurite.blocks.lock().unwrap().is_empty());
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[tokio::test]
+    async fn test_missing_sas() {
+        let mut azure = Azure::new();
+        azure.load_native(Arc::new(Azurite::default()).native());
+        let (bs, opts) = setup("sas", b"x");
+        assert!(matches!(azure.upload(&bs, serde_json::json!({}), &opts).await, Err(XError::SignFailed(_))));
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use crate::{error::XResult, Native, config::BucketSource, cloud_client::UploadOpts};
+use crate::resolver::sign::encode_path;
+use super::{
+    check_delete_xml, delete_xml_body, fetch_sts, object_exists, parse_list_xml, require_native,
+    ListPage, ObjectClient, ObjectMeta, Strategy, UrlRes,
+};
+
+// 表单直传类云厂商的差异点，其余流程（STS、上传、对象操作）由 FormStrategy 统一实现
+pub trait FormProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    // 从域名中解析出 bucket、region
+    fn domain_parser(&self, domain: &str) -> Value;
+
+    // 厂商要求的固定表单字段，STS 下发的 mergeFormData 会覆盖同名字段
+    fn form_fields(&self, _sts: &Value, _opts: &UploadOpts) -> Map<String, Value> {
+        Map::new()
+    }
+
+    // 返回 200 而不是默认的 204，部分客户端无法处理空响应
+    fn success_status(&self) -> Option<Value> {
+        None
+    }
+
+    fn file_field(&self) -> &str {
+        "file"
+    }
+
+    fn upload_url(&self, bucket_source: &BucketSource, _sts: &Value) -> String {
+        format!("https://{}", bucket_source.domain.as_deref().unwrap_or(""))
+    }
+
+    fn upload_result(&self, bucket_source: &BucketSource, base_url: String, opts: &UploadOpts) -> UrlRes {
+        UrlRes {
+            base_url,
+            key: opts.key.clone(),
+            domain: bucket_source.domain.clone().unwrap_or_default(),
+            bucket: bucket_source.name.clone(),
+        }
+    }
+
+    // 服务端复制时的源对象，作为 x-{name}-copy-source 头的值，默认 /<bucket>/<key>
+    fn copy_source(&self, source: &BucketSource, key: &str) -> String {
+        let location = self.domain_parser(source.domain.as_deref().unwrap_or(""));
+        format!("/{}/{}", location["bucket"].as_str().unwrap_or(""), encode_path(key))
+    }
+
+    // 批量删除的请求体：(body, content-type, response_type)
+    fn delete_body(&self, keys: &[String]) -> (String, &'static str, &'static str) {
+        (delete_xml_body(keys), "application/xml", "text")
+    }
+
+    fn check_delete(&self, res: &Value) -> XResult<()> {
+        check_delete_xml(res)
+    }
+
+    // 列举接口的 response_type 和解析函数
+    fn list_parser(&self) -> (&'static str, fn(&Value) -> XResult<ListPage>) {
+        ("text", parse_list_xml)
+    }
+}
+
+pub struct FormStrategy<P> {
+    provider: P,
+    native: Option<Arc<dyn Native>>,
+}
+
+impl<P: FormProvider> FormStrategy<P> {
+    pub fn with_provider(provider: P) -> Self {
+        Self { provider, native: None }
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    pub fn native(&self) -> XResult<&dyn Native> {
+        require_native(&self.native)
+    }
+}
+
+impl<P: FormProvider + Default> FormStrategy<P> {
+    pub fn new() -> Self {
+        Self::with_provider(P::default())
+    }
+}
+
+impl<P: FormProvider + Default> Default for FormStrategy<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl<P: FormProvider> Strategy for FormStrategy<P> {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    fn load_native(&mut self, native: Arc<dyn Native>) {
+        self.native = Some(native);
+    }
+
+    fn storage_key(&self, bucket_source: &BucketSource) -> String {
+        format!("sts:{}:{}",
+            bucket_source.cloud_name.as_deref().unwrap_or(""),
+            bucket_source.name
+        )
+    }
+
+    fn domain_parser(&self, domain: &str) -> Value {
+        self.provider.domain_parser(domain)
+    }
+
+    async fn get_sts(&self, bucket_source: &BucketSource) -> XResult<Value> {
+        fetch_sts(require_native(&self.native)?, &self.storage_key(bucket_source), bucket_source).await
+    }
+
+    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts) -> XResult<UrlRes> {
+        let native = require_native(&self.native)?;
+        let base_url = self.provider.upload_url(bucket_source, &sts);
+
+        let mut form_data = Map::new();
+        form_data.insert("key".to_string(), Value::String(opts.key.clone()));
+        if let Some(status) = self.provider.success_status() {
+            form_data.insert("success_action_status".to_string(), status);
+        }
+        form_data.extend(self.provider.form_fields(&sts, opts));
+        if let Some(merge_data) = sts["mergeFormData"].as_object() {
+            form_data.extend(merge_data.clone());
+        }
+
+        native.upload_file(crate::UploadArgs {
+            url: base_url.clone(),
+            name: self.provider.file_field().to_string(),
+            file_path: opts.file_path.clone(),
+            form_data: Value::Object(form_data),
+            on_progress: opts.on_progress.clone(),
+        }).await?;
+
+        Ok(self.provider.upload_result(bucket_source, base_url, opts))
+    }
+
+    async fn exists(&self, bucket_source: &BucketSource, key: &str) -> XResult<bool> {
+        object_exists(self.head(bucket_source, key).await)
+    }
+
+    async fn head(&self, bucket_source: &BucketSource, key: &str) -> XResult<ObjectMeta> {
+        ObjectClient::new(self, &self.native, bucket_source).await?.head(key).await
+    }
+
+    async fn delete(&self, bucket_source: &BucketSource, key: &str) -> XResult<()> {
+        ObjectClient::new(self, &self.native, bucket_source).await?.delete(key).await
+    }
+
+    async fn delete_many(&self, bucket_source: &BucketSource, keys: &[String]) -> XResult<()> {
+        let client = ObjectClient::new(self, &self.native, bucket_source).await?;
+        // 单次最多删除 1000 个对象
+        for chunk in keys.chunks(1000) {
+            let (body, content_type, response_type) = self.provider.delete_body(chunk);
+            let res = client.delete_batch(body, content_type, response_type).await?;
+            self.provider.check_delete(&res)?;
+        }
+        Ok(())
+    }
+
+    async fn copy(&self, source: &BucketSource, src_key: &str, target: &BucketSource, dest_key: &str) -> XResult<()> {
+        let header = format!("x-{}-copy-source", self.name());
+        let copy_source = self.provider.copy_source(source, src_key);
+        ObjectClient::new(self, &self.native, target).await?
+            .copy(dest_key, (&header, copy_source))
+            .await
+    }
+
+    async fn list(&self, bucket_source: &BucketSource, prefix: &str) -> XResult<Vec<ObjectMeta>> {
+        let (response_type, parse) = self.provider.list_parser();
+        ObjectClient::new(self, &self.native, bucket_source).await?
+            .list(prefix, response_type, parse)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::oss::OssProvider;
+    use crate::testing::{upload_fixture, ScriptedNative};
+
+    #[derive(Default)]
+    struct Upyun;
+
+    impl FormProvider for Upyun {
+        fn name(&self) -> &str {
+            "upyun"
+        }
+
+        fn domain_parser(&self, domain: &str) -> Value {
+            serde_json::json!({ "bucket": domain.split('.').next().unwrap_or("") })
+        }
+
+        fn form_fields(&self, _sts: &Value, opts: &UploadOpts) -> Map<String, Value> {
+            let mut fields = Map::new();
+            fields.insert("save-key".to_string(), Value::String(format!("/{}", opts.key)));
+            fields
+        }
+
+        fn upload_url(&self, bucket_source: &BucketSource, _sts: &Value) -> String {
+            format!("https://v0.api.upyun.com/{}", bucket_source.name)
+        }
+
+        fn copy_source(&self, source: &BucketSource, key: &str) -> String {
+            format!("/{}/{}", source.name, key)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_form_provider() {
+        let native = ScriptedNative::new();
+        native.on_request(|_| Ok(serde_json::json!({ "mergeFormData": { "policy": "p", "save-key": "/override" } })));
+        let mut strategy = FormStrategy::<Upyun>::new();
+        strategy.load_native(native.clone());
+        let (bs, opts) = upload_fixture("form", b"hello", serde_json::json!({
+            "name": "photos",
+            "domain": "photos.b0.upaiyun.com",
+        }), "_up/a.jpg");
+
+        let sts = strategy.get_sts(&bs).await.unwrap();
+        let res = strategy.upload(&bs, sts, &opts).await.unwrap();
+        assert_eq!(strategy.name(), "upyun");
+        assert_eq!(res.base_url, "https://v0.api.upyun.com/photos");
+        assert_eq!(res.key, "_up/a.jpg");
+
+        let uploads = native.uploads();
+        assert_eq!(uploads[0].url, "https://v0.api.upyun.com/photos");
+        assert_eq!(uploads[0].name, "file");
+        let form = &uploads[0].form_data;
+        assert_eq!(form["key"], "_up/a.jpg");
+        assert_eq!(form["policy"], "p");
+        assert_eq!(form["save-key"], "/override");
+        assert!(form.get("success_action_status").is_none());
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[test]
+    fn test_default_copy_source() {
+        let bs: BucketSource = serde_json::from_value(serde_json::json!({
+            "name": "img",
+            "domain": "img-bucket.oss-cn-hangzhou.aliyuncs.com",
+        })).unwrap();
+        assert_eq!(OssProvider.copy_source(&bs, "_oss/a b.jpg"), "/img-bucket/_oss/a%20b.jpg");
+        assert_eq!(Upyun.copy_source(&bs, "_up/a.jpg"), "/img/_up/a.jpg");
+    }
+}
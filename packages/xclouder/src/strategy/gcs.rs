@@ -0,0 +1,455 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::error::{XError, XResult};
+use crate::resolver::sign::{encode, encode_path};
+use crate::{config::BucketSource, cloud_client::UploadOpts, Native, RequestArgs, UploadPartArgs};
+use super::form::{FormProvider, FormStrategy};
+use super::{header_value, Strategy, UrlRes};
+
+const ENDPOINT: &str = "https://storage.googleapis.com";
+// 分片须为 256KB 的整数倍
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Default)]
+pub struct GcsProvider;
+
+impl GcsProvider {
+    // 服务端可在 STS 中指定 bucket，否则从 {bucket}.storage.googleapis.com 解析
+    fn bucket(&self, bucket_source: &BucketSource, sts: &Value) -> String {
+        sts["bucket"].as_str().map(|bucket| bucket.to_string())
+            .or_else(|| self.domain_parser(bucket_source.domain.as_deref().unwrap_or(""))["bucket"]
+                .as_str()
+                .map(|bucket| bucket.to_string()))
+            .unwrap_or_else(|| bucket_source.name.clone())
+    }
+}
+
+impl FormProvider for GcsProvider {
+    fn name(&self) -> &str {
+        "gcs"
+    }
+
+    fn domain_parser(&self, domain: &str) -> Value {
+        match domain.strip_suffix(".storage.googleapis.com") {
+            Some(bucket) => serde_json::json!({ "bucket": bucket }),
+            None => serde_json::json!({}),
+        }
+    }
+
+    fn success_status(&self) -> Option<Value> {
+        Some(Value::from("200"))
+    }
+
+    fn upload_url(&self, bucket_source: &BucketSource, sts: &Value) -> String {
+        format!("{}/{}", endpoint(sts), self.bucket(bucket_source, sts))
+    }
+
+    fn upload_result(&self, bucket_source: &BucketSource, _base_url: String, opts: &UploadOpts) -> UrlRes {
+        let domain = bucket_source.domain.clone().unwrap_or_default();
+        UrlRes {
+            base_url: bucket_source.base_url(&domain),
+            key: opts.key.clone(),
+            domain,
+            bucket: bucket_source.name.clone(),
+        }
+    }
+
+    fn copy_source(&self, source: &BucketSource, key: &str) -> String {
+        format!("/{}/{}", self.bucket(source, &Value::Null), encode_path(key))
+    }
+}
+
+// STS 为签名好的 POST policy（mergeFormData）时走表单上传，
+// 为短期 OAuth token（accessToken）时走可续传的 resumable session
+pub struct Gcs {
+    form: FormStrategy<GcsProvider>,
+    chunk_size: u64,
+}
+
+impl Gcs {
+    pub fn new() -> Self {
+        Self {
+            form: FormStrategy::new(),
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    // https://cloud.google.com/storage/docs/performing-resumable-uploads
+    async fn resumable_upload(&self, bucket_source: &BucketSource, sts: &Value, opts: &UploadOpts) -> XResult<()> {
+        let native = self.form.native()?;
+        let token = sts["accessToken"].as_str().unwrap_or("");
+        let auth = ("Authorization".to_string(), format!("Bearer {}", token));
+        let size = std::fs::metadata(&opts.file_path)
+            .map_err(|e| XError::UploadFailed(e.to_string()))?
+            .len();
+
+        // session 有效期一周，同一文件同一 key 复用
+        let state_key = format!("gcs:upload:{}:{}", opts.file_path, opts.key);
+        let saved = native.get_storage(&state_key)
+            .filter(|state| state["size"].as_u64() == Some(size))
+            .and_then(|state| state["session"].as_str().map(|session| session.to_string()));
+        let (session, mut offset) = match saved {
+            Some(session) => match self.uploaded(native, &session, size).await {
+                Ok(Some(offset)) => (session, offset),
+                // 上次最后一片已经传完，只是没来得及清理状态
+                Ok(None) => {
+                    native.del_storage(&state_key);
+                    if let Some(on_progress) = &opts.on_progress {
+                        on_progress(1.0);
+                    }
+                    return Ok(());
+                }
+                // session 过期返回 404/410，重新创建
+                Err(XError::NotFound(_)) => (self.create_session(native, bucket_source, sts, opts, size, &auth).await?, 0),
+                Err(e) => return Err(e),
+            },
+            None => (self.create_session(native, bucket_source, sts, opts, size, &auth).await?, 0),
+        };
+        native.set_storage(&state_key, serde_json::json!({ "session": session, "size": size }));
+
+        loop {
+            let len = self.chunk_size.min(size - offset);
+            let range = if size == 0 {
+                "bytes */0".to_string()
+            } else {
+                format!("bytes {}-{}/{}", offset, offset + len - 1, size)
+            };
+            let res = native.upload_part(UploadPartArgs {
+                method: "PUT".to_string(),
+                url: session.clone(),
+                headers: vec![auth.clone(), ("Content-Range".to_string(), range)],
+                file_path: opts.file_path.clone(),
+                offset,
+                size: len,
+                response_type: "headers".to_string(),
+            }).await?;
+            // 308 的 Range 是服务端实际保存的字节，可能少于本次发送的，从该位置继续；
+            // 没有 Range 时，最后一片是 200 上传完成，否则是 308 一个字节都没保存，从头开始
+            offset = match received(&res, size) {
+                Some(received) => received,
+                None if offset + len >= size => size,
+                None => 0,
+            };
+
+            if let Some(on_progress) = &opts.on_progress {
+                on_progress(if size == 0 { 1.0 } else { offset as f32 / size as f32 });
+            }
+            if offset >= size {
+                break;
+            }
+        }
+        native.del_storage(&state_key);
+        Ok(())
+    }
+
+    async fn create_session(
+        &self,
+        native: &dyn Native,
+        bucket_source: &BucketSource,
+        sts: &Value,
+        opts: &UploadOpts,
+        size: u64,
+        auth: &(String, String),
+    ) -> XResult<String> {
+        let res = native.request(RequestArgs {
+            method: "POST".to_string(),
+            url: format!("{}/upload/storage/v1/b/{}/o?uploadType=resumable&name={}",
+                endpoint(sts),
+                self.form.provider().bucket(bucket_source, sts),
+                encode(&opts.key)),
+            enable_cache: false,
+            timeout: 10000,
+            response_type: "headers".to_string(),
+            headers: vec![
+                auth.clone(),
+                ("X-Upload-Content-Length".to_string(), size.to_string()),
+            ],
+            body: None,
+        }).await?;
+        header_value(&res, "location")
+            .ok_or_else(|| XError::UploadFailed("gcs resumable session has no location".to_string()))
+    }
+
+    // 查询 session 已保存的字节数，308 响应的 Range 为 bytes=0-N，没有 Range 表示还没有保存；
+    // session 已完成时返回 200 和对象的 JSON 元数据，此时返回 None
+    async fn uploaded(&self, native: &dyn Native, session: &str, size: u64) -> XResult<Option<u64>> {
+        let res = native.request(RequestArgs {
+            method: "PUT".to_string(),
+            url: session.to_string(),
+            enable_cache: false,
+            timeout: 10000,
+            response_type: "headers".to_string(),
+            headers: vec![("Content-Range".to_string(), format!("bytes */{}", size))],
+            body: None,
+        }).await?;
+        if header_value(&res, "range").is_none() && header_value(&res, "content-type").is_some_and(|t| t.contains("json")) {
+            return Ok(None);
+        }
+        Ok(Some(received(&res, size).unwrap_or(0)))
+    }
+}
+
+// 308 响应头 Range: bytes=0-N 表示已收到 N+1 字节
+fn received(res: &Value, size: u64) -> Option<u64> {
+    header_value(res, "range")
+        .and_then(|range| range.rsplit('-').next().and_then(|end| end.parse::<u64>().ok()))
+        .map(|end| (end + 1).min(size))
+}
+
+impl Default for Gcs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Strategy for Gcs {
+    fn name(&self) -> &str {
+        self.form.name()
+    }
+
+    fn load_native(&mut self, native: Arc<dyn Native>) {
+        self.form.load_native(native);
+    }
+
+    fn storage_key(&self, bucket_source: &BucketSource) -> String {
+        self.form.storage_key(bucket_source)
+    }
+
+    fn domain_parser(&self, domain: &str) -> Value {
+        self.form.domain_parser(domain)
+    }
+
+    async fn get_sts(&self, bucket_source: &BucketSource) -> XResult<Value> {
+        self.form.get_sts(bucket_source).await
+    }
+
+    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts) -> XResult<UrlRes> {
+        if sts["accessToken"].is_string() {
+            self.resumable_upload(bucket_source, &sts, opts).await?;
+            return Ok(self.form.provider().upload_result(bucket_source, String::new(), opts));
+        }
+        if !sts["mergeFormData"].is_object() {
+            return Err(XError::SignFailed("gcs credentials missing".to_string()));
+        }
+        self.form.upload(bucket_source, sts, opts).await
+    }
+}
+
+fn endpoint(sts: &Value) -> String {
+    sts["endpoint"].as_str()
+        .map(|endpoint| endpoint.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| ENDPOINT.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::sync::Mutex;
+    use crate::testing::{upload_fixture, ScriptedNative};
+    use crate::UploadArgs;
+
+    const FAKE: &str = "http://localhost:4443";
+
+    // 模拟 fake-gcs-server：表单上传、resumable session 及其状态查询
+    #[derive(Default)]
+    struct FakeGcs {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+        sessions: Mutex<HashMap<String, (String, Vec<u8>)>>,
+        creates: Mutex<usize>,
+        // 收到该 offset 的分片时断开连接
+        fail_at: Mutex<Option<u64>>,
+        // 下一个分片只收下的字节数
+        short: Mutex<Option<u64>>,
+    }
+
+    // 传输层把不带 Location 的 308 按成功返回响应头，尚未收到数据时没有 Range
+    fn resume_incomplete(data: &[u8]) -> Value {
+        if data.is_empty() {
+            return serde_json::json!({ "Content-Length": "0" });
+        }
+        serde_json::json!({ "Content-Length": "0", "Range": format!("bytes=0-{}", data.len() - 1) })
+    }
+
+    // 最后一片或已完成的 session 返回 200，正文为对象元数据
+    fn finalized() -> Value {
+        serde_json::json!({ "Content-Type": "application/json; charset=UTF-8", "ETag": "CJjQ" })
+    }
+
+    fn read(path: &str, offset: u64, size: u64) -> Vec<u8> {
+        let mut file = std::fs::File::open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        let mut data = vec![0u8; size as usize];
+        file.read_exact(&mut data).unwrap();
+        data
+    }
+
+    impl FakeGcs {
+        fn post_policy(&self, args: &UploadArgs) -> XResult<()> {
+            let bucket = args.url.strip_prefix(&format!("{}/", FAKE)).unwrap();
+            assert!(args.form_data["policy"].is_string());
+            assert_eq!(args.form_data["x-goog-algorithm"], "GOOG4-RSA-SHA256");
+            let key = args.form_data["key"].as_str().unwrap();
+            let size = std::fs::metadata(&args.file_path).unwrap().len();
+            self.objects.lock().unwrap().insert(format!("{}/{}", bucket, key), read(&args.file_path, 0, size));
+            Ok(())
+        }
+
+        fn put_chunk(&self, args: &UploadPartArgs) -> XResult<Value> {
+            if *self.fail_at.lock().unwrap() == Some(args.offset) {
+                return Err(XError::NetworkError("connection reset".to_string()));
+            }
+            assert!(args.headers.contains(&("Authorization".to_string(), "Bearer ya29".to_string())));
+            let range = &args.headers.iter().find(|(k, _)| k == "Content-Range").unwrap().1;
+            let mut sessions = self.sessions.lock().unwrap();
+            let (name, data) = sessions.get_mut(&args.url).ok_or_else(|| XError::NotFound(args.url.clone()))?;
+            assert_eq!(*range, format!("bytes {}-{}/{}", args.offset, args.offset + args.size - 1,
+                range.rsplit('/').next().unwrap()));
+            assert_eq!(data.len() as u64, args.offset);
+            assert_eq!(args.response_type, "headers");
+            // 模拟服务端只收下分片的前 short 字节
+            let short = self.short.lock().unwrap().take().unwrap_or(args.size);
+            data.extend(read(&args.file_path, args.offset, short));
+            if range.ends_with(&format!("/{}", data.len())) {
+                let (name, data) = (name.clone(), data.clone());
+                self.objects.lock().unwrap().insert(name, data);
+                return Ok(finalized());
+            }
+            Ok(resume_incomplete(data))
+        }
+
+        fn session(&self, args: &RequestArgs) -> XResult<Value> {
+            if args.method == "POST" {
+                let rest = args.url.strip_prefix(&format!("{}/upload/storage/v1/b/", FAKE)).unwrap();
+                let (bucket, query) = rest.split_once("/o?").unwrap();
+                let name = query.split('&').find_map(|p| p.strip_prefix("name=")).unwrap();
+                let name = percent_encoding::percent_decode_str(name).decode_utf8_lossy().to_string();
+                let mut creates = self.creates.lock().unwrap();
+                *creates += 1;
+                let session = format!("{}/upload/storage/v1/b/{}/o?uploadType=resumable&upload_id={}", FAKE, bucket, creates);
+                self.sessions.lock().unwrap().insert(session.clone(), (format!("{}/{}", bucket, name), Vec::new()));
+                return Ok(serde_json::json!({ "Location": session }));
+            }
+            let sessions = self.sessions.lock().unwrap();
+            let (name, data) = sessions.get(&args.url).ok_or_else(|| XError::NotFound(args.url.clone()))?;
+            if self.objects.lock().unwrap().get(name).is_some_and(|object| object.len() == data.len()) {
+                return Ok(finalized());
+            }
+            Ok(resume_incomplete(data))
+        }
+
+        fn native(self: &Arc<Self>) -> Arc<ScriptedNative> {
+            let native = ScriptedNative::new();
+            let gcs = self.clone();
+            native.on_upload(move |args| gcs.post_policy(args));
+            let gcs = self.clone();
+            native.on_upload_part(move |args| gcs.put_chunk(args));
+            let gcs = self.clone();
+            native.on_request(move |args| gcs.session(args));
+            native
+        }
+    }
+
+    fn setup(name: &str, data: &[u8]) -> (BucketSource, UploadOpts) {
+        upload_fixture(name, data, serde_json::json!({
+            "name": "img",
+            "domain": "acme-img.storage.googleapis.com",
+            "cloud": "gcs",
+            "cloudName": "_gcs",
+        }), "_gcs/u1/a.jpg")
+    }
+
+    #[tokio::test]
+    async fn test_signed_policy_upload() {
+        let fake = Arc::new(FakeGcs::default());
+        let mut gcs = Gcs::new();
+        let native = fake.native();
+        gcs.load_native(native.clone());
+        let (bs, opts) = setup("policy", b"hello");
+        let sts = serde_json::json!({
+            "endpoint": FAKE,
+            "mergeFormData": {
+                "policy": "eyJjb25kaXRpb25zIjpbXX0=",
+                "x-goog-algorithm": "GOOG4-RSA-SHA256",
+                "x-goog-credential": "sa@acme.iam.gserviceaccount.com/20261018/auto/storage/goog4_request",
+                "x-goog-date": "20261018T000000Z",
+                "x-goog-signature": "00ff",
+            }
+        });
+
+        let res = gcs.upload(&bs, sts, &opts).await.unwrap();
+        assert_eq!(res.to_string(), "https://acme-img.storage.googleapis.com/_gcs/u1/a.jpg");
+        assert_eq!(fake.objects.lock().unwrap()["acme-img/_gcs/u1/a.jpg"], b"hello");
+        assert!(matches!(gcs.upload(&bs, serde_json::json!({}), &opts).await, Err(XError::SignFailed(_))));
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[tokio::test]
+    async fn test_resumable_session() {
+        let fake = Arc::new(FakeGcs::default());
+        let mut gcs = Gcs::new().with_chunk_size(4);
+        let native = fake.native();
+        gcs.load_native(native.clone());
+        let (bs, opts) = setup("resumable", b"0123456789");
+        let sts = serde_json::json!({ "endpoint": FAKE, "accessToken": "ya29" });
+
+        *fake.fail_at.lock().unwrap() = Some(8);
+        assert!(gcs.upload(&bs, sts.clone(), &opts).await.is_err());
+        assert_eq!(native.storage_keys().len(), 1);
+
+        // 续传时查询 session 进度，只补传最后一个分片
+        *fake.fail_at.lock().unwrap() = None;
+        let res = gcs.upload(&bs, sts.clone(), &opts).await.unwrap();
+        assert_eq!(res.to_string(), "https://acme-img.storage.googleapis.com/_gcs/u1/a.jpg");
+        assert_eq!(fake.objects.lock().unwrap()["acme-img/_gcs/u1/a.jpg"], b"0123456789");
+        assert_eq!(*fake.creates.lock().unwrap(), 1);
+        assert!(native.storage_keys().is_empty());
+
+        // 已完成的 session 不再上传
+        let parts = native.parts().len();
+        native.set_storage(&format!("gcs:upload:{}:{}", opts.file_path, opts.key),
+            serde_json::json!({ "session": format!("{}/upload/storage/v1/b/acme-img/o?uploadType=resumable&upload_id=1", FAKE), "size": 10 }));
+        gcs.upload(&bs, sts.clone(), &opts).await.unwrap();
+        assert_eq!(native.parts().len(), parts);
+        assert_eq!(*fake.creates.lock().unwrap(), 1);
+        assert!(native.storage_keys().is_empty());
+
+        // session 失效后重新创建
+        native.set_storage(&format!("gcs:upload:{}:{}", opts.file_path, opts.key),
+            serde_json::json!({ "session": format!("{}/expired", FAKE), "size": 10 }));
+        gcs.upload(&bs, serde_json::json!({ "endpoint": FAKE, "accessToken": "ya29" }), &opts).await.unwrap();
+        assert_eq!(*fake.creates.lock().unwrap(), 2);
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[tokio::test]
+    async fn test_resumable_partial_chunk() {
+        let fake = Arc::new(FakeGcs::default());
+        let mut gcs = Gcs::new().with_chunk_size(4);
+        let native = fake.native();
+        gcs.load_native(native.clone());
+        let (bs, opts) = setup("partial", b"0123456789");
+        let sts = serde_json::json!({ "endpoint": FAKE, "accessToken": "ya29" });
+
+        // 服务端只收下第一个分片的 2 字节，按 308 的 Range 从 offset 2 继续
+        *fake.short.lock().unwrap() = Some(2);
+        gcs.upload(&bs, sts.clone(), &opts).await.unwrap();
+        assert_eq!(fake.objects.lock().unwrap()["acme-img/_gcs/u1/a.jpg"], b"0123456789");
+
+        // 服务端一个字节都没保存，308 不带 Range，从 0 重新上传
+        fake.objects.lock().unwrap().clear();
+        *fake.short.lock().unwrap() = Some(0);
+        gcs.upload(&bs, sts, &opts).await.unwrap();
+        assert_eq!(fake.objects.lock().unwrap()["acme-img/_gcs/u1/a.jpg"], b"0123456789");
+        assert_eq!(native.parts().iter().skip(3).map(|p| p.offset).collect::<Vec<_>>(), vec![0, 0, 4, 8]);
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::error::{XError, XResult};
+use crate::utils::file_md5;
+use crate::{config::BucketSource, cloud_client::UploadOpts, Native};
+use super::{ObjectMeta, Strategy, UrlRes};
+
+// 本地目录存储，用于离线开发和集成测试，配置中 cloud 为 local。
+// 对象保存在 {root}/{bucket}/{key}，bucket 的 domain 可配置为 file:///{root}/{bucket}
+// 或静态服务的地址，未配置时上传返回 file:// 地址
+pub struct LocalFs {
+    root: PathBuf,
+}
+
+impl LocalFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn bucket_dir(&self, bucket_source: &BucketSource) -> PathBuf {
+        self.root.join(&bucket_source.name)
+    }
+
+    fn object_path(&self, bucket_source: &BucketSource, key: &str) -> XResult<PathBuf> {
+        // key 不能跳出 bucket 目录
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(XError::InvalidKey(key.to_string()));
+        }
+        Ok(self.bucket_dir(bucket_source).join(relative))
+    }
+
+    fn base_url(&self, bucket_source: &BucketSource) -> String {
+        match bucket_source.domain.as_deref() {
+            Some(domain) => bucket_source.base_url(domain),
+            None => format!("file://{}", self.bucket_dir(bucket_source).to_string_lossy()),
+        }
+    }
+}
+
+fn io_error(key: &str, e: std::io::Error) -> XError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => XError::NotFound(key.to_string()),
+        _ => XError::RequestFailed(format!("{}: {}", key, e)),
+    }
+}
+
+fn copy_file(from: &Path, to: &Path, key: &str) -> XResult<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| io_error(key, e))?;
+    }
+    std::fs::copy(from, to).map_err(|e| io_error(key, e))?;
+    Ok(())
+}
+
+fn collect_keys(dir: &Path, prefix: &str, keys: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let key = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+        if entry.file_type()?.is_dir() {
+            collect_keys(&entry.path(), &key, keys)?;
+        } else {
+            keys.push(key);
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Strategy for LocalFs {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    // 不依赖宿主能力
+    fn load_native(&mut self, _native: Arc<dyn Native>) {}
+
+    fn storage_key(&self, bucket_source: &BucketSource) -> String {
+        format!("sts:{}:{}",
+            bucket_source.cloud_name.as_deref().unwrap_or(""),
+            bucket_source.name
+        )
+    }
+
+    fn domain_parser(&self, _domain: &str) -> Value {
+        serde_json::json!({})
+    }
+
+    async fn get_sts(&self, _bucket_source: &BucketSource) -> XResult<Value> {
+        Ok(Value::Null)
+    }
+
+    async fn upload(&self, bucket_source: &BucketSource, _sts: Value, opts: &UploadOpts) -> XResult<UrlRes> {
+        let path = self.object_path(bucket_source, &opts.key)?;
+        copy_file(Path::new(&opts.file_path), &path, &opts.key)
+            .map_err(|e| XError::UploadFailed(e.to_string()))?;
+        if let Some(on_progress) = &opts.on_progress {
+            on_progress(1.0);
+        }
+        Ok(UrlRes {
+            base_url: self.base_url(bucket_source),
+            key: opts.key.clone(),
+            domain: bucket_source.domain.clone().unwrap_or_default(),
+            bucket: bucket_source.name.clone(),
+        })
+    }
+
+    async fn exists(&self, bucket_source: &BucketSource, key: &str) -> XResult<bool> {
+        Ok(self.object_path(bucket_source, key)?.is_file())
+    }
+
+    async fn head(&self, bucket_source: &BucketSource, key: &str) -> XResult<ObjectMeta> {
+        let path = self.object_path(bucket_source, key)?;
+        let metadata = std::fs::metadata(&path).map_err(|e| io_error(key, e))?;
+        if !metadata.is_file() {
+            return Err(XError::NotFound(key.to_string()));
+        }
+        Ok(ObjectMeta {
+            key: key.to_string(),
+            size: metadata.len(),
+            etag: Some(file_md5(&path.to_string_lossy())?),
+            content_type: None,
+            last_modified: metadata.modified().ok()
+                .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc2822()),
+        })
+    }
+
+    // 与对象存储一致，删除不存在的对象不报错
+    async fn delete(&self, bucket_source: &BucketSource, key: &str) -> XResult<()> {
+        match std::fs::remove_file(self.object_path(bucket_source, key)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(key, e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn copy(&self, source: &BucketSource, src_key: &str, target: &BucketSource, dest_key: &str) -> XResult<()> {
+        copy_file(&self.object_path(source, src_key)?, &self.object_path(target, dest_key)?, src_key)
+    }
+
+    async fn list(&self, bucket_source: &BucketSource, prefix: &str) -> XResult<Vec<ObjectMeta>> {
+        let dir = self.bucket_dir(bucket_source);
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut keys = Vec::new();
+        collect_keys(&dir, "", &mut keys).map_err(|e| io_error(prefix, e))?;
+        keys.retain(|key| key.starts_with(prefix));
+        keys.sort();
+
+        let mut objects = Vec::new();
+        for key in keys {
+            objects.push(self.head(bucket_source, &key).await?);
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::config::Config;
+    use crate::resolver::{self, parse::parse_url, ResolveOptions};
+    use crate::testing::upload_fixture;
+
+    fn root(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xclouder-local-{}-{}", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn test_object_operations() {
+        let root = root("objects");
+        let local = LocalFs::new(&root);
+        let (bs, opts) = upload_fixture("src.jpg", b"hello", serde_json::json!({ "name": "img", "cloud": "local" }), "_local/u1/a.jpg");
+
+        let res = local.upload(&bs, Value::Null, &opts).await.unwrap();
+        assert_eq!(res.to_string(), format!("file://{}/img/_local/u1/a.jpg", root.to_string_lossy()));
+        assert!(local.exists(&bs, "_local/u1/a.jpg").await.unwrap());
+
+        let meta = local.head(&bs, "_local/u1/a.jpg").await.unwrap();
+        assert_eq!(meta.size, 5);
+        assert_eq!(meta.etag.as_deref(), Some("5d41402abc4b2a76b9719d911017c592"));
+
+        local.copy(&bs, "_local/u1/a.jpg", &bs, "_local/u2/b.jpg").await.unwrap();
+        let keys: Vec<String> = local.list(&bs, "_local/").await.unwrap().into_iter().map(|o| o.key).collect();
+        assert_eq!(keys, vec!["_local/u1/a.jpg", "_local/u2/b.jpg"]);
+
+        local.delete(&bs, "_local/u1/a.jpg").await.unwrap();
+        local.delete(&bs, "_local/u1/a.jpg").await.unwrap();
+        assert!(matches!(local.head(&bs, "_local/u1/a.jpg").await, Err(XError::NotFound(_))));
+        assert!(matches!(local.head(&bs, "../src.jpg").await, Err(XError::InvalidKey(_))));
+        assert_eq!(local.list(&bs, "_local/u1").await.unwrap(), vec![]);
+
+        let _ = std::fs::remove_dir_all(&root);
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[test]
+    fn test_resolve_local() {
+        let config = Config::from_json(serde_json::json!({
+            "cloudSource": [{
+                "name": "_local",
+                "cloud": "local",
+                "buckets": [
+                    { "name": "img", "domain": "file:///data/xclouder/img" },
+                    { "name": "video", "domain": "http://localhost:8080/video" }
+                ]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        let branch = |index: usize| HashMap::from([("_local".to_string(), Arc::new(config.cloud_source[0].buckets[index].clone()))]);
+        let opts = ResolveOptions { config: Some(&config), unhealthy_domains: None, clouds: None };
+
+        let url = resolver::resolve(&branch(0), "_local/a.jpg", &[], &opts).unwrap();
+        assert_eq!(url, "file:///data/xclouder/img/_local/a.jpg");
+        let url = resolver::resolve(&branch(1), "_local/a.mp4", &[], &opts).unwrap();
+        assert_eq!(url, "http://localhost:8080/video/_local/a.mp4");
+
+        let parsed = parse_url(&config, &HashMap::new(), "file:///data/xclouder/img/_local/a.jpg").unwrap();
+        assert_eq!((parsed.bucket.as_str(), parsed.key.as_str()), ("img", "_local/a.jpg"));
+        let parsed = parse_url(&config, &HashMap::new(), "http://localhost:8080/video/_local/a.mp4").unwrap();
+        assert_eq!((parsed.bucket.as_str(), parsed.key.as_str()), ("video", "_local/a.mp4"));
+    }
+}
@@ -1,21 +1,357 @@
 pub mod cos;
 pub mod tos;
 pub mod oss;
+pub mod xml;
+pub mod form;
+pub mod qiniu;
+pub mod azure;
+pub mod gcs;
+pub mod local;
 
+use std::sync::Arc;
 use async_trait::async_trait;
+use base64::Engine;
+use md5::{Digest, Md5};
 use serde_json::Value;
-use crate::error::XResult;
+use crate::error::{XError, XResult};
 use crate::config::BucketSource;
-use crate::{Native, UploadOpts};
+use crate::resolver::sign::{self, Query, SignRequest};
+use crate::{Native, RequestArgs, UploadOpts};
 
 #[async_trait]
 pub trait Strategy: Send + Sync {
     fn name(&self) -> &str;
-    fn load_native(&mut self, native: Box<dyn Native>);
+    fn load_native(&mut self, native: Arc<dyn Native>);
     fn storage_key(&self, bucket_source: &BucketSource) -> String;
     fn domain_parser(&self, domain: &str) -> Value;
-    async fn get_sts(&self, bucket_source: &BucketSource, opts: &UploadOpts<'_>) -> XResult<Value>;
-    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts<'_>) -> XResult<UrlRes>;
+    async fn get_sts(&self, bucket_source: &BucketSource) -> XResult<Value>;
+    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts) -> XResult<UrlRes>;
+
+    // 对象是否已存在，用于秒传；默认视为不存在
+    async fn exists(&self, _bucket_source: &BucketSource, _key: &str) -> XResult<bool> {
+        Ok(false)
+    }
+
+    async fn head(&self, _bucket_source: &BucketSource, _key: &str) -> XResult<ObjectMeta> {
+        Err(unsupported(self.name(), "head"))
+    }
+
+    async fn delete(&self, _bucket_source: &BucketSource, _key: &str) -> XResult<()> {
+        Err(unsupported(self.name(), "delete"))
+    }
+
+    // 没有批量接口的云逐个删除
+    async fn delete_many(&self, bucket_source: &BucketSource, keys: &[String]) -> XResult<()> {
+        for key in keys {
+            self.delete(bucket_source, key).await?;
+        }
+        Ok(())
+    }
+
+    // 同一家云内服务端复制，用 target 的凭证发起
+    async fn copy(&self, _source: &BucketSource, _src_key: &str, _target: &BucketSource, _dest_key: &str) -> XResult<()> {
+        Err(unsupported(self.name(), "copy"))
+    }
+
+    async fn list(&self, _bucket_source: &BucketSource, _prefix: &str) -> XResult<Vec<ObjectMeta>> {
+        Err(unsupported(self.name(), "list"))
+    }
+}
+
+// 缓存的临时凭证是否可用，没有 expireAt 的视为不过期
+pub fn sts_valid(cache: &Value) -> bool {
+    cache["expireAt"].as_i64().is_none_or(|expire_at| expire_at > chrono::Utc::now().timestamp())
+}
+
+// 向服务端申请临时凭证，带 expireAt 的结果缓存到 storage，过期后重新申请
+pub async fn fetch_sts(native: &dyn Native, storage_key: &str, bucket_source: &BucketSource) -> XResult<Value> {
+    if let Some(cache) = native.get_storage(storage_key) {
+        if sts_valid(&cache) {
+            return Ok(cache);
+        }
+        native.del_storage(storage_key);
+    }
+
+    let bucket_key = bucket_source.domain.as_ref()
+        .map(|domain| domain.split('.').next().unwrap_or(""))
+        .unwrap_or("");
+
+    let res = native.request(RequestArgs {
+        method: "GET".to_string(),
+        url: format!("/api/cloud/sts?cloud={}&cloudName={}&bucket={}",
+            bucket_source.cloud.as_deref().unwrap_or(""),
+            bucket_source.cloud_name.as_deref().unwrap_or(""),
+            bucket_key
+        ),
+        enable_cache: false,
+        timeout: 10000,
+        response_type: "json".to_string(),
+        headers: vec![],
+        body: None,
+    }).await?;
+
+    if res["expireAt"].as_i64().is_some() {
+        native.set_storage(storage_key, res.clone());
+    }
+
+    Ok(res)
+}
+
+pub fn unsupported(cloud: &str, op: &str) -> XError {
+    XError::UnsupportedOperation { cloud: cloud.to_string(), op: op.to_string() }
+}
+
+// 策略未注入 Native 时直接报错，不能返回空的 STS 或假装上传成功
+pub fn require_native(native: &Option<Arc<dyn Native>>) -> XResult<&dyn Native> {
+    native.as_deref().ok_or(XError::NativeNotLoaded)
+}
+
+// 从 response_type 为 headers 的响应中取值，头名大小写不敏感
+pub fn header_value(headers: &Value, name: &str) -> Option<String> {
+    headers.as_object()
+        .and_then(|map| map.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)))
+        .and_then(|(_, v)| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectMeta {
+    pub key: String,
+    pub size: u64,
+    pub etag: Option<String>,
+    pub content_type: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl ObjectMeta {
+    // HEAD 响应头
+    pub fn from_headers(key: &str, headers: &Value) -> Self {
+        let header = |name: &str| header_value(headers, name);
+        Self {
+            key: key.to_string(),
+            size: header("content-length").and_then(|v| v.parse().ok()).unwrap_or(0),
+            etag: header("etag").map(|v| v.trim_matches('"').to_string()),
+            content_type: header("content-type"),
+            last_modified: header("last-modified"),
+        }
+    }
+}
+
+pub struct ListPage {
+    pub objects: Vec<ObjectMeta>,
+    pub next_marker: Option<String>,
+}
+
+// COS 和 OSS 的 ListObjects 响应格式一致
+pub fn parse_list_xml(res: &Value) -> XResult<ListPage> {
+    let body = res.as_str().ok_or_else(|| XError::RequestFailed("invalid list response".to_string()))?;
+    let objects: Vec<ObjectMeta> = xml::blocks(body, "Contents").into_iter()
+        .filter_map(|item| Some(ObjectMeta {
+            key: xml::text(item, "Key")?,
+            size: xml::text(item, "Size").and_then(|v| v.parse().ok()).unwrap_or(0),
+            etag: xml::text(item, "ETag").map(|v| v.trim_matches('"').to_string()),
+            content_type: None,
+            last_modified: xml::text(item, "LastModified"),
+        }))
+        .collect();
+    let next_marker = match xml::text(body, "IsTruncated").as_deref() {
+        Some("true") => xml::text(body, "NextMarker")
+            .filter(|marker| !marker.is_empty())
+            .or_else(|| objects.last().map(|object| object.key.clone())),
+        _ => None,
+    };
+    Ok(ListPage { objects, next_marker })
+}
+
+pub fn delete_xml_body(keys: &[String]) -> String {
+    let objects: String = keys.iter()
+        .map(|key| format!("<Object><Key>{}</Key></Object>", xml::escape(key)))
+        .collect();
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?><Delete><Quiet>true</Quiet>{}</Delete>", objects)
+}
+
+// Quiet 模式下只返回删除失败的对象
+pub fn check_delete_xml(res: &Value) -> XResult<()> {
+    let body = res.as_str().unwrap_or("");
+    match xml::blocks(body, "Error").first() {
+        Some(error) => Err(XError::RequestFailed(format!("delete {} failed: {}",
+            xml::text(error, "Key").unwrap_or_default(),
+            xml::text(error, "Code").unwrap_or_default()))),
+        None => Ok(()),
+    }
+}
+
+pub fn content_md5(body: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(Md5::digest(body.as_bytes()))
+}
+
+pub struct ObjectRequest<'a> {
+    pub method: &'a str,
+    pub key: &'a str,
+    pub query: Query,
+    pub response_type: &'a str,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl<'a> ObjectRequest<'a> {
+    pub fn new(method: &'a str, key: &'a str, response_type: &'a str) -> Self {
+        Self {
+            method,
+            key,
+            query: vec![],
+            response_type,
+            headers: vec![],
+            body: None,
+        }
+    }
+}
+
+// 用 STS 临时密钥生成预签名地址，再通过 native 发起对象请求
+pub async fn signed_request(
+    native: &dyn Native,
+    cloud: &str,
+    bucket_source: &BucketSource,
+    location: &Value,
+    sts: &Value,
+    req: ObjectRequest<'_>,
+) -> XResult<Value> {
+    let credentials = Credentials::from_sts(sts)
+        .ok_or_else(|| XError::SignFailed("missing credentials in sts".to_string()))?;
+    let domain = bucket_source.domain.as_deref().ok_or(XError::InvalidConfig)?;
+    let bucket = location["bucket"].as_str()
+        .unwrap_or_else(|| domain.split('.').next().unwrap_or(""));
+    let url = sign::presign(cloud, &SignRequest {
+        method: req.method,
+        host: domain,
+        bucket,
+        region: location["region"].as_str().unwrap_or(""),
+        path: &format!("/{}", req.key),
+        query: req.query,
+        headers: req.headers.clone(),
+        now: chrono::Utc::now().timestamp(),
+        expires: 600,
+    }, &credentials)?;
+
+    native.request(RequestArgs {
+        method: req.method.to_string(),
+        url,
+        enable_cache: false,
+        timeout: 10000,
+        response_type: req.response_type.to_string(),
+        headers: req.headers,
+        body: req.body,
+    }).await
+}
+
+pub fn object_exists<T>(res: XResult<T>) -> XResult<bool> {
+    match res {
+        Ok(_) => Ok(true),
+        Err(XError::NotFound(_)) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+// 对象管理请求的上下文：native、签名所需的位置信息和 STS
+pub struct ObjectClient<'a> {
+    pub native: &'a dyn Native,
+    pub cloud: &'a str,
+    pub bucket_source: &'a BucketSource,
+    pub location: Value,
+    pub sts: Value,
+}
+
+impl<'a> ObjectClient<'a> {
+    pub async fn new(strategy: &'a dyn Strategy, native: &'a Option<Arc<dyn Native>>, bucket_source: &'a BucketSource) -> XResult<Self> {
+        let native = require_native(native)?;
+        Ok(Self {
+            native,
+            cloud: strategy.name(),
+            bucket_source,
+            location: strategy.domain_parser(bucket_source.domain.as_deref().unwrap_or("")),
+            sts: strategy.get_sts(bucket_source).await?,
+        })
+    }
+
+    pub async fn request(&self, req: ObjectRequest<'_>) -> XResult<Value> {
+        signed_request(self.native, self.cloud, self.bucket_source, &self.location, &self.sts, req).await
+    }
+
+    pub async fn head(&self, key: &str) -> XResult<ObjectMeta> {
+        let headers = self.request(ObjectRequest::new("HEAD", key, "headers")).await?;
+        Ok(ObjectMeta::from_headers(key, &headers))
+    }
+
+    pub async fn delete(&self, key: &str) -> XResult<()> {
+        self.request(ObjectRequest::new("DELETE", key, "text")).await?;
+        Ok(())
+    }
+
+    pub async fn copy(&self, dest_key: &str, copy_source: (&str, String)) -> XResult<()> {
+        let mut req = ObjectRequest::new("PUT", dest_key, "text");
+        req.headers.push((copy_source.0.to_string(), copy_source.1));
+        self.request(req).await?;
+        Ok(())
+    }
+
+    // POST /?delete，body 需要带 Content-MD5
+    pub async fn delete_batch(&self, body: String, content_type: &str, response_type: &str) -> XResult<Value> {
+        let mut req = ObjectRequest::new("POST", "", response_type);
+        req.query.push(("delete".to_string(), None));
+        req.headers.push(("Content-MD5".to_string(), content_md5(&body)));
+        req.headers.push(("Content-Type".to_string(), content_type.to_string()));
+        req.body = Some(body);
+        self.request(req).await
+    }
+
+    // 按 marker 翻页直到列完
+    pub async fn list(&self, prefix: &str, response_type: &str, parse: fn(&Value) -> XResult<ListPage>) -> XResult<Vec<ObjectMeta>> {
+        let mut objects = Vec::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let mut req = ObjectRequest::new("GET", "", response_type);
+            req.query.push(("prefix".to_string(), Some(prefix.to_string())));
+            req.query.push(("max-keys".to_string(), Some("1000".to_string())));
+            if let Some(marker) = &marker {
+                req.query.push(("marker".to_string(), Some(marker.clone())));
+            }
+            let page = parse(&self.request(req).await?)?;
+            objects.extend(page.objects);
+            match page.next_marker {
+                Some(next) if marker.as_deref() != Some(next.as_str()) => marker = Some(next),
+                _ => break,
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub secret_id: String,
+    pub secret_key: String,
+    pub session_token: Option<String>,
+    pub cdn_auth_key: Option<String>,
+}
+
+impl Credentials {
+    // 兼容腾讯云 / 阿里云 / 火山引擎 STS 返回的字段名
+    pub fn from_sts(sts: &Value) -> Option<Self> {
+        let creds = sts.get("credentials").unwrap_or(sts);
+        let pick = |names: &[&str]| names.iter()
+            .find_map(|name| creds[*name].as_str())
+            .map(|s| s.to_string());
+
+        Some(Self {
+            secret_id: pick(&["tmpSecretId", "secretId", "accessKeyId"])?,
+            secret_key: pick(&["tmpSecretKey", "secretKey", "accessKeySecret", "secretAccessKey"])?,
+            session_token: pick(&["sessionToken", "securityToken"]),
+            cdn_auth_key: pick(&["cdnAuthKey"]).or_else(|| sts["cdnAuthKey"].as_str().map(|s| s.to_string())),
+        })
+    }
 }
 
 pub struct UrlRes {
@@ -80,3 +416,119 @@ impl ToString for UrlRes {
 //         })
 //     }
 // } 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use crate::testing::ScriptedNative;
+
+    fn setup(strategy: &mut dyn Strategy, domain: &str, responses: Vec<Value>) -> (Arc<ScriptedNative>, BucketSource) {
+        let bucket_source: BucketSource = serde_json::from_value(serde_json::json!({
+            "name": "test",
+            "domain": domain,
+            "cloud": strategy.name(),
+            "cloudName": "_main",
+        })).unwrap();
+        let native = ScriptedNative::new();
+        let responses = Mutex::new(VecDeque::from(responses));
+        native.on_request(move |_| Ok(responses.lock().unwrap().pop_front().unwrap_or(Value::Null)));
+        native.set_storage(&strategy.storage_key(&bucket_source), serde_json::json!({
+            "credentials": { "tmpSecretId": "id", "tmpSecretKey": "key" },
+        }));
+        strategy.load_native(native.clone());
+        (native, bucket_source)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_sts_refreshes_expired() {
+        let now = chrono::Utc::now().timestamp();
+        let native = ScriptedNative::new();
+        let bs: BucketSource = serde_json::from_value(serde_json::json!({ "name": "img", "cloud": "azure", "cloudName": "_azure" })).unwrap();
+
+        native.set_storage("sts:_azure:img", serde_json::json!({ "token": "old", "expireAt": now + 60 }));
+        assert_eq!(fetch_sts(native.as_ref(), "sts:_azure:img", &bs).await.unwrap()["token"], "old");
+        native.set_storage("sts:_azure:img", serde_json::json!({ "token": "old", "expireAt": now - 1 }));
+        assert_eq!(fetch_sts(native.as_ref(), "sts:_azure:img", &bs).await.unwrap()["token"], "sts-1");
+        assert_eq!(native.sts_requests(), 1);
+        assert_eq!(native.get_storage("sts:_azure:img").unwrap()["token"], "sts-1");
+    }
+
+    #[tokio::test]
+    async fn test_cos_object_operations() {
+        let mut cos = cos::Cos::new();
+        let (native, bs) = setup(&mut cos, "test-1250000000.cos.ap-guangzhou.myqcloud.com", vec![
+            serde_json::json!({ "Content-Length": "12", "ETag": "\"abc\"", "content-type": "image/jpeg" }),
+            Value::String("<ListBucketResult><IsTruncated>true</IsTruncated><NextMarker>_main/a.jpg</NextMarker>\
+                <Contents><Key>_main/a.jpg</Key><Size>1</Size></Contents></ListBucketResult>".to_string()),
+            Value::String("<ListBucketResult><IsTruncated>false</IsTruncated>\
+                <Contents><Key>_main/b.jpg</Key><Size>2</Size></Contents></ListBucketResult>".to_string()),
+            Value::String("<DeleteResult></DeleteResult>".to_string()),
+            Value::String(String::new()),
+        ]);
+
+        let meta = cos.head(&bs, "_main/a.jpg").await.unwrap();
+        assert_eq!(meta.size, 12);
+        assert_eq!(meta.etag.as_deref(), Some("abc"));
+        assert_eq!(meta.content_type.as_deref(), Some("image/jpeg"));
+
+        let objects = cos.list(&bs, "_main/").await.unwrap();
+        assert_eq!(objects.iter().map(|o| o.key.as_str()).collect::<Vec<_>>(), vec!["_main/a.jpg", "_main/b.jpg"]);
+
+        let keys = vec!["_main/a.jpg".to_string(), "_main/b&c.jpg".to_string()];
+        cos.delete_many(&bs, &keys).await.unwrap();
+        cos.copy(&bs, "_main/a.jpg", &bs, "_main/c.jpg").await.unwrap();
+
+        let requests = native.requests();
+        assert_eq!(requests[0].method, "HEAD");
+        assert!(requests[0].url.starts_with("https://test-1250000000.cos.ap-guangzhou.myqcloud.com/_main/a.jpg?"));
+        assert!(!requests[1].url.contains("marker="));
+        assert!(requests[2].url.contains("&marker=_main/a.jpg"));
+
+        let delete = &requests[3];
+        assert_eq!(delete.method, "POST");
+        assert!(delete.url.contains("&delete"));
+        let body = delete.body.as_deref().unwrap();
+        assert!(body.contains("<Key>_main/b&amp;c.jpg</Key>"));
+        assert!(delete.headers.contains(&("Content-MD5".to_string(), content_md5(body))));
+
+        assert_eq!(requests[4].method, "PUT");
+        assert!(requests[4].url.contains("/_main/c.jpg?"));
+        assert!(requests[4].headers.contains(&(
+            "x-cos-copy-source".to_string(),
+            "test-1250000000.cos.ap-guangzhou.myqcloud.com/_main/a.jpg".to_string(),
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_tos_object_operations() {
+        let mut tos = tos::Tos::new();
+        let (native, bs) = setup(&mut tos, "test.tos-cn-beijing.volces.com", vec![
+            serde_json::json!({ "IsTruncated": false, "Contents": [{ "Key": "_main/a.jpg", "Size": 3, "ETag": "\"e\"" }] }),
+            serde_json::json!({ "Error": [{ "Key": "_main/a.jpg", "Code": "AccessDenied" }] }),
+        ]);
+
+        let objects = tos.list(&bs, "_main/").await.unwrap();
+        assert_eq!(objects, vec![ObjectMeta {
+            key: "_main/a.jpg".to_string(),
+            size: 3,
+            etag: Some("e".to_string()),
+            ..Default::default()
+        }]);
+
+        let err = tos.delete_many(&bs, &["_main/a.jpg".to_string()]).await.unwrap_err();
+        assert!(matches!(err, XError::RequestFailed(_)));
+        let requests = native.requests();
+        assert!(requests[1].body.as_deref().unwrap().contains("\"Quiet\":true"));
+    }
+
+    #[tokio::test]
+    async fn test_object_operations_without_native() {
+        let cos = cos::Cos::new();
+        let bs: BucketSource = serde_json::from_value(serde_json::json!({ "name": "test", "domain": "a.cos.b.myqcloud.com" })).unwrap();
+        assert!(matches!(cos.exists(&bs, "_main/a.jpg").await, Err(XError::NativeNotLoaded)));
+        assert!(matches!(cos.get_sts(&bs).await, Err(XError::NativeNotLoaded)));
+        assert!(matches!(cos.delete(&bs, "_main/a.jpg").await, Err(XError::NativeNotLoaded)));
+    }
+}
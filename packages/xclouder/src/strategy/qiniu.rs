@@ -0,0 +1,323 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use base64::Engine;
+use serde_json::{Map, Value};
+use crate::error::{XError, XResult};
+use crate::{config::BucketSource, cloud_client::UploadOpts, Native, RequestArgs, UploadPartArgs};
+use super::form::{FormProvider, FormStrategy};
+use super::{Strategy, UrlRes};
+
+// 分片大小，同时也是表单上传的上限，超过后走分片上传 v2
+const PART_SIZE: u64 = 4 * 1024 * 1024;
+
+#[derive(Default)]
+pub struct QiniuProvider;
+
+impl FormProvider for QiniuProvider {
+    fn name(&self) -> &str {
+        "qiniu"
+    }
+
+    fn domain_parser(&self, domain: &str) -> Value {
+        // {bucket}.{region}.qiniucs.com，其余为自定义 CDN 域名，无法解析
+        let parts: Vec<&str> = domain.split('.').collect();
+        if parts.len() == 4 && domain.ends_with(".qiniucs.com") {
+            serde_json::json!({
+                "bucket": parts[0],
+                "region": parts[1],
+            })
+        } else {
+            serde_json::json!({})
+        }
+    }
+
+    fn form_fields(&self, sts: &Value, _opts: &UploadOpts) -> Map<String, Value> {
+        let mut fields = Map::new();
+        if let Some(token) = sts["token"].as_str() {
+            fields.insert("token".to_string(), Value::String(token.to_string()));
+        }
+        fields
+    }
+
+    fn upload_url(&self, _bucket_source: &BucketSource, sts: &Value) -> String {
+        up_host(sts)
+    }
+
+    // 上传域名和访问域名不同，返回的地址使用访问域名
+    fn upload_result(&self, bucket_source: &BucketSource, _base_url: String, opts: &UploadOpts) -> UrlRes {
+        UrlRes {
+            base_url: format!("https://{}", bucket_source.domain.as_deref().unwrap_or("")),
+            key: opts.key.clone(),
+            domain: bucket_source.domain.clone().unwrap_or_default(),
+            bucket: bucket_source.name.clone(),
+        }
+    }
+}
+
+// 七牛的对象操作不兼容 S3 协议，只提供上传
+pub struct Qiniu {
+    form: FormStrategy<QiniuProvider>,
+    part_size: u64,
+}
+
+impl Qiniu {
+    pub fn new() -> Self {
+        Self {
+            form: FormStrategy::new(),
+            part_size: PART_SIZE,
+        }
+    }
+
+    pub fn with_part_size(mut self, part_size: u64) -> Self {
+        self.part_size = part_size.max(1);
+        self
+    }
+
+    // https://developer.qiniu.com/kodo/6364/multipartupload-interface
+    async fn multipart_upload(&self, bucket_source: &BucketSource, sts: &Value, opts: &UploadOpts, size: u64) -> XResult<()> {
+        let native = self.form.native()?;
+        let token = sts["token"].as_str()
+            .ok_or_else(|| XError::SignFailed("qiniu upload token missing".to_string()))?;
+        let bucket = token_bucket(sts)
+            .or_else(|| sts["bucket"].as_str().map(|bucket| bucket.to_string()))
+            .unwrap_or_else(|| bucket_source.name.clone());
+        let base = format!("{}/buckets/{}/objects/{}/uploads",
+            up_host(sts),
+            bucket,
+            base64::engine::general_purpose::URL_SAFE.encode(&opts.key));
+        let auth = ("Authorization".to_string(), format!("UpToken {}", token));
+
+        // 断点续传：同一文件同一 key 的 uploadId 和已完成的分片记在 storage 中
+        let state_key = format!("qiniu:upload:{}:{}", opts.file_path, opts.key);
+        let mut state = native.get_storage(&state_key)
+            .filter(|state| state["size"].as_u64() == Some(size)
+                && state["expireAt"].as_i64().unwrap_or(0) > chrono::Utc::now().timestamp());
+        if state.is_none() {
+            let res = native.request(RequestArgs {
+                method: "POST".to_string(),
+                url: base.clone(),
+                enable_cache: false,
+                timeout: 10000,
+                response_type: "json".to_string(),
+                headers: vec![auth.clone()],
+                body: None,
+            }).await?;
+            let upload_id = res["uploadId"].as_str()
+                .ok_or_else(|| XError::UploadFailed("qiniu init multipart upload failed".to_string()))?;
+            let init = serde_json::json!({
+                "uploadId": upload_id,
+                "expireAt": res["expireAt"],
+                "size": size,
+                "parts": [],
+            });
+            native.set_storage(&state_key, init.clone());
+            state = Some(init);
+        }
+        let mut state = state.unwrap_or_default();
+        let upload_id = state["uploadId"].as_str().unwrap_or("").to_string();
+
+        let count = size.div_ceil(self.part_size);
+        for part_number in 1..=count {
+            let done = state["parts"].as_array().into_iter().flatten()
+                .any(|part| part["partNumber"].as_u64() == Some(part_number));
+            if done {
+                continue;
+            }
+            let offset = (part_number - 1) * self.part_size;
+            let res = native.upload_part(UploadPartArgs {
+                method: "PUT".to_string(),
+                url: format!("{}/{}/{}", base, upload_id, part_number),
+                headers: vec![
+                    auth.clone(),
+                    ("Content-Type".to_string(), "application/octet-stream".to_string()),
+                ],
+                file_path: opts.file_path.clone(),
+                offset,
+                size: self.part_size.min(size - offset),
+                response_type: "json".to_string(),
+            }).await?;
+            let etag = res["etag"].as_str()
+                .ok_or_else(|| XError::UploadFailed(format!("qiniu part {} has no etag", part_number)))?;
+            if let Some(parts) = state["parts"].as_array_mut() {
+                parts.push(serde_json::json!({ "partNumber": part_number, "etag": etag }));
+            }
+            native.set_storage(&state_key, state.clone());
+
+            if let Some(on_progress) = &opts.on_progress {
+                on_progress((offset + self.part_size).min(size) as f32 / size as f32);
+            }
+        }
+
+        let mut parts = state["parts"].as_array().cloned().unwrap_or_default();
+        parts.sort_by_key(|part| part["partNumber"].as_u64());
+        native.request(RequestArgs {
+            method: "POST".to_string(),
+            url: format!("{}/{}", base, upload_id),
+            enable_cache: false,
+            timeout: 10000,
+            response_type: "json".to_string(),
+            headers: vec![auth, ("Content-Type".to_string(), "application/json".to_string())],
+            body: Some(serde_json::json!({ "parts": parts, "fname": opts.filename }).to_string()),
+        }).await?;
+        native.del_storage(&state_key);
+        Ok(())
+    }
+}
+
+impl Default for Qiniu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Strategy for Qiniu {
+    fn name(&self) -> &str {
+        self.form.name()
+    }
+
+    fn load_native(&mut self, native: Arc<dyn Native>) {
+        self.form.load_native(native);
+    }
+
+    fn storage_key(&self, bucket_source: &BucketSource) -> String {
+        self.form.storage_key(bucket_source)
+    }
+
+    fn domain_parser(&self, domain: &str) -> Value {
+        self.form.domain_parser(domain)
+    }
+
+    // 服务端下发 { token, region?, upHost?, expireAt }
+    async fn get_sts(&self, bucket_source: &BucketSource) -> XResult<Value> {
+        self.form.get_sts(bucket_source).await
+    }
+
+    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts) -> XResult<UrlRes> {
+        let size = std::fs::metadata(&opts.file_path)
+            .map_err(|e| XError::UploadFailed(e.to_string()))?
+            .len();
+        if size <= self.part_size {
+            return self.form.upload(bucket_source, sts, opts).await;
+        }
+        self.multipart_upload(bucket_source, &sts, opts, size).await?;
+        Ok(self.form.provider().upload_result(bucket_source, up_host(&sts), opts))
+    }
+}
+
+// 上传域名按区域区分：z0 华东、z1 华北、z2 华南、na0 北美、as0 东南亚等
+fn up_host(sts: &Value) -> String {
+    if let Some(host) = sts["upHost"].as_str() {
+        return if host.contains("://") { host.to_string() } else { format!("https://{}", host) };
+    }
+    format!("https://up-{}.qiniup.com", sts["region"].as_str().unwrap_or("z0"))
+}
+
+// 上传凭证为 AccessKey:签名:base64(putPolicy)，scope 为 bucket 或 bucket:key
+fn token_bucket(sts: &Value) -> Option<String> {
+    let policy = sts["token"].as_str()?.rsplit(':').next()?;
+    let policy = base64::engine::general_purpose::URL_SAFE.decode(policy).ok()?;
+    let policy: Value = serde_json::from_slice(&policy).ok()?;
+    let scope = policy["scope"].as_str()?;
+    Some(scope.split(':').next().unwrap_or(scope).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::testing::{upload_fixture, ScriptedNative};
+
+    // 模拟七牛上传接口：初始化返回 uploadId，分片返回 etag，第 n 个分片可编排为失败
+    fn qiniu_native(fail_part: Arc<Mutex<Option<u64>>>) -> Arc<ScriptedNative> {
+        let native = ScriptedNative::new();
+        native.on_upload_part(move |args| {
+            let part_number: u64 = args.url.rsplit('/').next().unwrap().parse().unwrap();
+            if *fail_part.lock().unwrap() == Some(part_number) {
+                return Err(XError::NetworkError("reset".to_string()));
+            }
+            Ok(serde_json::json!({ "etag": format!("etag-{}", part_number), "md5": "" }))
+        });
+        native.on_request(|args| {
+            if args.url.ends_with("/uploads") {
+                return Ok(serde_json::json!({ "uploadId": "u1", "expireAt": chrono::Utc::now().timestamp() + 3600 }));
+            }
+            Ok(serde_json::json!({ "key": "ok" }))
+        });
+        native
+    }
+
+    fn token(scope: &str) -> String {
+        let policy = serde_json::json!({ "scope": scope, "deadline": 1 }).to_string();
+        format!("ak:sign:{}", base64::engine::general_purpose::URL_SAFE.encode(policy))
+    }
+
+    fn setup(name: &str, size: usize) -> (BucketSource, UploadOpts) {
+        upload_fixture(name, &vec![7u8; size], serde_json::json!({
+            "name": "legacy",
+            "domain": "img.example.com",
+            "cloud": "qiniu",
+            "cloudName": "_qiniu",
+        }), "_qiniu/a.jpg")
+    }
+
+    #[tokio::test]
+    async fn test_form_upload() {
+        let native = qiniu_native(Default::default());
+        let (bs, opts) = setup("form", 10);
+        let mut qiniu = Qiniu::new();
+        qiniu.load_native(native.clone());
+        let sts = serde_json::json!({ "token": token("photos"), "region": "z2" });
+
+        let res = qiniu.upload(&bs, sts, &opts).await.unwrap();
+        assert_eq!(res.to_string(), "https://img.example.com/_qiniu/a.jpg");
+
+        let forms = native.uploads();
+        assert_eq!(forms[0].url, "https://up-z2.qiniup.com");
+        assert_eq!(forms[0].form_data["key"], "_qiniu/a.jpg");
+        assert_eq!(forms[0].form_data["token"], token("photos"));
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload_resume() {
+        let fail_part = Arc::new(Mutex::new(Some(2)));
+        let native = qiniu_native(fail_part.clone());
+        let (bs, opts) = setup("multipart", 10);
+        let mut qiniu = Qiniu::new().with_part_size(4);
+        qiniu.load_native(native.clone());
+        let sts = serde_json::json!({ "token": token("photos:_qiniu/a.jpg"), "upHost": "up.example.com" });
+
+        assert!(qiniu.upload(&bs, sts.clone(), &opts).await.is_err());
+        assert_eq!(native.parts().len(), 1);
+
+        *fail_part.lock().unwrap() = None;
+        let res = qiniu.upload(&bs, sts, &opts).await.unwrap();
+        assert_eq!(res.to_string(), "https://img.example.com/_qiniu/a.jpg");
+
+        // 续传时不再初始化，只上传剩余的两个分片
+        let parts = native.parts();
+        let base = "https://up.example.com/buckets/photos/objects/X3Fpbml1L2EuanBn/uploads";
+        assert_eq!(parts.iter().map(|p| (p.url.as_str(), p.offset, p.size)).collect::<Vec<_>>(), vec![
+            (format!("{}/u1/1", base).as_str(), 0, 4),
+            (format!("{}/u1/2", base).as_str(), 4, 4),
+            (format!("{}/u1/3", base).as_str(), 8, 2),
+        ]);
+        let requests = native.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, base);
+        assert_eq!(requests[1].url, format!("{}/u1", base));
+        assert!(requests[1].headers.contains(&("Authorization".to_string(), format!("UpToken {}", token("photos:_qiniu/a.jpg")))));
+        let body: Value = serde_json::from_str(requests[1].body.as_deref().unwrap()).unwrap();
+        assert_eq!(body["parts"][2], serde_json::json!({ "partNumber": 3, "etag": "etag-3" }));
+        assert!(native.storage_keys().is_empty());
+        let _ = std::fs::remove_file(&opts.file_path);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        assert_eq!(token_bucket(&serde_json::json!({ "token": token("photos:a.jpg") })).as_deref(), Some("photos"));
+        assert_eq!(token_bucket(&serde_json::json!({ "token": "bad" })), None);
+        assert_eq!(up_host(&serde_json::json!({})), "https://up-z0.qiniup.com");
+    }
+}
@@ -1,111 +1,71 @@
-use async_trait::async_trait;
 use serde_json::Value;
-use crate::{error::XResult, Native, config::BucketSource, cloud_client::UploadOpts};
-use super::{Strategy, UrlRes};
+use crate::error::{XError, XResult};
+use super::form::{FormProvider, FormStrategy};
+use super::{ListPage, ObjectMeta};
 
-pub struct Tos {
-    name: String,
-    native: Option<Box<dyn Native>>,
-}
+pub type Tos = FormStrategy<TosProvider>;
 
-impl Tos {
-    pub fn new() -> Self {
-        Self {
-            name: "tos".to_string(),
-            native: None,
-        }
-    }
-}
+#[derive(Default)]
+pub struct TosProvider;
 
-#[async_trait]
-impl Strategy for Tos {
+impl FormProvider for TosProvider {
     fn name(&self) -> &str {
-        &self.name
-    }
-
-    fn load_native(&mut self, native: Box<dyn Native>) {
-        self.native = Some(native);
-    }
-
-    fn storage_key(&self, bucket_source: &BucketSource) -> String {
-        format!("sts:{}:{}", 
-            bucket_source.cloud_name.as_deref().unwrap_or(""),
-            bucket_source.name
-        )
-    }
-
-    fn domain_parser(&self, _domain: &str) -> Value {
-        // TOS 不需要特殊的域名解析
-        serde_json::json!({})
+        "tos"
     }
 
-    async fn get_sts(&self, bucket_source: &BucketSource, opts: &UploadOpts<'_>) -> XResult<Value> {
-        if let Some(native) = &self.native {
-            let storage_key = format!("sts:{}:{}", 
-                bucket_source.cloud_name.as_deref().unwrap_or(""),
-                bucket_source.name);
-                
-            let cache = native.get_storage(&storage_key);
-            if let Some(cache) = cache {
-                return Ok(cache);
-            }
-
-            let bucket_key = bucket_source.domain.as_ref()
-                .map(|domain| domain.split('.').next().unwrap_or(""))
-                .unwrap_or("");
-
-            let res = native.request(crate::RequestArgs {
-                method: "GET".to_string(),
-                url: format!("/api/cloud/sts?cloud={}&cloudName={}&bucket={}", 
-                    bucket_source.cloud.as_deref().unwrap_or(""),
-                    bucket_source.cloud_name.as_deref().unwrap_or(""),
-                    bucket_key
-                ),
-                enable_cache: false,
-                timeout: 10000,
-                response_type: "json".to_string(),
-            }).await?;
-
-            if let Some(expire_at) = res["expireAt"].as_i64() {
-                native.set_storage(&storage_key, res.clone());
-            }
-
-            Ok(res)
+    fn domain_parser(&self, domain: &str) -> Value {
+        // {bucket}.tos-cn-beijing.volces.com
+        let parts: Vec<&str> = domain.split('.').collect();
+        if parts.len() >= 3 {
+            serde_json::json!({
+                "bucket": parts[0],
+                "region": parts[1].trim_start_matches("tos-"),
+            })
         } else {
-            Ok(Value::Null)
+            serde_json::json!({})
         }
     }
 
-    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts<'_>) -> XResult<UrlRes> {
-        let base_url = format!("https://{}", bucket_source.domain.as_deref().unwrap_or(""));
-        
-        if let Some(native) = &self.native {
-            let mut form_data = serde_json::json!({
-                "key": opts.key,
-            });
-
-            if let Some(merge_data) = sts["mergeFormData"].as_object() {
-                if let Some(obj) = form_data.as_object_mut() {
-                    for (k, v) in merge_data {
-                        obj.insert(k.clone(), v.clone());
-                    }
-                }
-            }
+    fn delete_body(&self, keys: &[String]) -> (String, &'static str, &'static str) {
+        let body = serde_json::json!({
+            "Quiet": true,
+            "Objects": keys.iter().map(|key| serde_json::json!({ "Key": key })).collect::<Vec<_>>(),
+        });
+        (body.to_string(), "application/json", "json")
+    }
 
-            native.upload_file(crate::UploadArgs {
-                url: base_url.clone(),
-                name: "file".to_string(),
-                file_path: opts.file_path.clone(),
-                form_data,
-                on_progress: opts.on_progress.clone(),
-            }).await?;
+    fn check_delete(&self, res: &Value) -> XResult<()> {
+        if let Some(error) = res["Error"].as_array().and_then(|errors| errors.first()) {
+            return Err(XError::RequestFailed(format!("delete {} failed: {}",
+                error["Key"].as_str().unwrap_or(""),
+                error["Code"].as_str().unwrap_or(""))));
         }
+        Ok(())
+    }
 
-        Ok(UrlRes {
-            base_url,
-            key: opts.key.clone(),
-            domain: bucket_source.domain.clone().unwrap_or_default(),
-            bucket: bucket_source.name.clone(),
-        })
+    fn list_parser(&self) -> (&'static str, fn(&Value) -> XResult<ListPage>) {
+        ("json", parse_list_json)
     }
-} 
\ No newline at end of file
+}
+
+// TOS 的 ListObjects 返回 JSON
+fn parse_list_json(res: &Value) -> XResult<ListPage> {
+    let objects: Vec<ObjectMeta> = res["Contents"].as_array().into_iter().flatten()
+        .filter_map(|item| Some(ObjectMeta {
+            key: item["Key"].as_str()?.to_string(),
+            size: item["Size"].as_u64().unwrap_or(0),
+            etag: item["ETag"].as_str().map(|v| v.trim_matches('"').to_string()),
+            content_type: None,
+            last_modified: item["LastModified"].as_str().map(|v| v.to_string()),
+        }))
+        .collect();
+    let next_marker = if res["IsTruncated"].as_bool().unwrap_or(false) {
+        res["NextMarker"].as_str()
+            .filter(|marker| !marker.is_empty())
+            .map(|marker| marker.to_string())
+            .or_else(|| objects.last().map(|object| object.key.clone()))
+    } else {
+        None
+    };
+    Ok(ListPage { objects, next_marker })
+}
@@ -0,0 +1,57 @@
+// 对象存储接口返回的 XML 结构简单，按标签截取即可，不引入完整的解析器
+
+pub fn blocks<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+    let mut blocks = Vec::new();
+    while let Some(start) = rest.find(&open) {
+        let body = &rest[start + open.len()..];
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        blocks.push(&body[..end]);
+        rest = &body[end + close.len()..];
+    }
+    blocks
+}
+
+pub fn text(xml: &str, tag: &str) -> Option<String> {
+    blocks(xml, tag).first().map(|value| unescape(value))
+}
+
+pub fn escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml() {
+        let xml = r#"<ListBucketResult><IsTruncated>false</IsTruncated>
+            <Contents><Key>_cos/a&amp;b.jpg</Key><Size>10</Size></Contents>
+            <Contents><Key>_cos/c.jpg</Key><Size>20</Size></Contents>
+        </ListBucketResult>"#;
+        let contents = blocks(xml, "Contents");
+        assert_eq!(contents.len(), 2);
+        assert_eq!(text(contents[0], "Key").as_deref(), Some("_cos/a&b.jpg"));
+        assert_eq!(text(contents[1], "Size").as_deref(), Some("20"));
+        assert_eq!(text(xml, "IsTruncated").as_deref(), Some("false"));
+        assert_eq!(text(xml, "NextMarker"), None);
+        assert_eq!(escape("a&b<c>"), "a&amp;b&lt;c&gt;");
+    }
+}
@@ -0,0 +1,555 @@
+// 测试辅助（testing feature）：内存对象存储策略，以及可以编排故障的 Native，
+// 用于覆盖 upload_fn 的重试、域名切换和网络检测逻辑；各云的模拟服务端通过 on_* 接管传输
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use md5::{Digest, Md5};
+use serde_json::Value;
+use crate::cloud_client::UploadOpts;
+use crate::config::BucketSource;
+use crate::error::{XError, XResult};
+use crate::strategy::{fetch_sts, require_native, ObjectMeta, Strategy, UrlRes};
+use crate::{DownloadArgs, Native, NetworkInfo, RequestArgs, UploadArgs, UploadPartArgs};
+
+#[derive(Default)]
+pub struct MemoryStore {
+    objects: Mutex<BTreeMap<(String, String), Vec<u8>>>,
+    // 为 true 时 exists/head 返回网络错误
+    unavailable: AtomicBool,
+}
+
+impl MemoryStore {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn put(&self, bucket: &str, key: &str, data: Vec<u8>) {
+        if let Ok(mut objects) = self.objects.lock() {
+            objects.insert((bucket.to_string(), key.to_string()), data);
+        }
+    }
+
+    pub fn get(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        self.objects.lock().ok()?.get(&(bucket.to_string(), key.to_string())).cloned()
+    }
+
+    pub fn remove(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        self.objects.lock().ok()?.remove(&(bucket.to_string(), key.to_string()))
+    }
+
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.unavailable.store(unavailable, Ordering::SeqCst);
+    }
+
+    fn check_available(&self) -> XResult<()> {
+        if self.unavailable.load(Ordering::SeqCst) {
+            return Err(XError::NetworkError("memory store unavailable".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        self.objects.lock()
+            .map(|objects| objects.keys()
+                .filter(|(b, _)| b == bucket)
+                .map(|(_, key)| key.clone())
+                .collect())
+            .unwrap_or_default()
+    }
+}
+
+// 上传经过 Native::upload_file，故障由 ScriptedNative 决定；成功后文件内容写入 MemoryStore
+pub struct MemoryStrategy {
+    name: String,
+    store: Arc<MemoryStore>,
+    native: Option<Arc<dyn Native>>,
+}
+
+impl MemoryStrategy {
+    pub fn new(name: &str, store: Arc<MemoryStore>) -> Self {
+        Self {
+            name: name.to_string(),
+            store,
+            native: None,
+        }
+    }
+
+    fn meta(key: &str, data: &[u8]) -> ObjectMeta {
+        ObjectMeta {
+            key: key.to_string(),
+            size: data.len() as u64,
+            etag: Some(hex::encode(Md5::digest(data))),
+            content_type: None,
+            last_modified: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Strategy for MemoryStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn load_native(&mut self, native: Arc<dyn Native>) {
+        self.native = Some(native);
+    }
+
+    fn storage_key(&self, bucket_source: &BucketSource) -> String {
+        format!("sts:{}:{}",
+            bucket_source.cloud_name.as_deref().unwrap_or(""),
+            bucket_source.name
+        )
+    }
+
+    fn domain_parser(&self, _domain: &str) -> Value {
+        serde_json::json!({})
+    }
+
+    async fn get_sts(&self, bucket_source: &BucketSource) -> XResult<Value> {
+        fetch_sts(require_native(&self.native)?, &self.storage_key(bucket_source), bucket_source).await
+    }
+
+    async fn upload(&self, bucket_source: &BucketSource, sts: Value, opts: &UploadOpts) -> XResult<UrlRes> {
+        let native = require_native(&self.native)?;
+        let domain = bucket_source.domain.clone().unwrap_or_default();
+        let base_url = bucket_source.base_url(&domain);
+        native.upload_file(UploadArgs {
+            url: base_url.clone(),
+            name: "file".to_string(),
+            file_path: opts.file_path.clone(),
+            form_data: serde_json::json!({ "key": opts.key, "token": sts["token"] }),
+            on_progress: opts.on_progress.clone(),
+        }).await?;
+
+        let data = std::fs::read(&opts.file_path).map_err(|e| XError::UploadFailed(e.to_string()))?;
+        self.store.put(&bucket_source.name, &opts.key, data);
+        Ok(UrlRes {
+            base_url,
+            key: opts.key.clone(),
+            domain,
+            bucket: bucket_source.name.clone(),
+        })
+    }
+
+    async fn exists(&self, bucket_source: &BucketSource, key: &str) -> XResult<bool> {
+        self.store.check_available()?;
+        Ok(self.store.get(&bucket_source.name, key).is_some())
+    }
+
+    async fn head(&self, bucket_source: &BucketSource, key: &str) -> XResult<ObjectMeta> {
+        self.store.check_available()?;
+        self.store.get(&bucket_source.name, key)
+            .map(|data| Self::meta(key, &data))
+            .ok_or_else(|| XError::NotFound(key.to_string()))
+    }
+
+    async fn delete(&self, bucket_source: &BucketSource, key: &str) -> XResult<()> {
+        self.store.remove(&bucket_source.name, key);
+        Ok(())
+    }
+
+    async fn copy(&self, source: &BucketSource, src_key: &str, target: &BucketSource, dest_key: &str) -> XResult<()> {
+        let data = self.store.get(&source.name, src_key)
+            .ok_or_else(|| XError::NotFound(src_key.to_string()))?;
+        self.store.put(&target.name, dest_key, data);
+        Ok(())
+    }
+
+    async fn list(&self, bucket_source: &BucketSource, prefix: &str) -> XResult<Vec<ObjectMeta>> {
+        Ok(self.store.keys(&bucket_source.name).into_iter()
+            .filter(|key| key.starts_with(prefix))
+            .filter_map(|key| self.store.get(&bucket_source.name, &key).map(|data| Self::meta(&key, &data)))
+            .collect())
+    }
+}
+
+pub type UploadHandler = Arc<dyn Fn(&UploadArgs) -> XResult<()> + Send + Sync>;
+pub type UploadPartHandler = Arc<dyn Fn(&UploadPartArgs) -> XResult<Value> + Send + Sync>;
+pub type DownloadHandler = Arc<dyn Fn(&DownloadArgs) -> XResult<()> + Send + Sync>;
+pub type RequestHandler = Arc<dyn Fn(&RequestArgs) -> XResult<Value> + Send + Sync>;
+
+#[derive(Default)]
+struct Script {
+    storage: HashMap<String, Value>,
+    // 域名 -> 剩余失败次数
+    upload_failures: HashMap<String, usize>,
+    dns_failures: HashSet<String>,
+    latency: Duration,
+    expired_sts: usize,
+    expired_tokens: HashSet<String>,
+    // 依次返回的网络类型，最后一个保持不变
+    network_types: VecDeque<String>,
+    attempts: Vec<String>,
+    sts_requests: usize,
+    upload_handler: Option<UploadHandler>,
+    upload_part_handler: Option<UploadPartHandler>,
+    download_handler: Option<DownloadHandler>,
+    request_handler: Option<RequestHandler>,
+    // 处理成功的调用，按顺序记录
+    uploads: Vec<UploadArgs>,
+    parts: Vec<UploadPartArgs>,
+    downloads: Vec<DownloadArgs>,
+    requests: Vec<RequestArgs>,
+}
+
+// 可编排故障的 Native，所有方法可在测试过程中随时调用以改变后续行为
+#[derive(Default)]
+pub struct ScriptedNative {
+    script: Mutex<Script>,
+}
+
+impl ScriptedNative {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn script(&self) -> std::sync::MutexGuard<'_, Script> {
+        self.script.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 上传到该域名的前 times 次失败，usize::MAX 表示一直失败
+    pub fn fail_uploads(&self, domain: &str, times: usize) {
+        self.script().upload_failures.insert(domain.to_string(), times);
+    }
+
+    // 该域名 DNS 解析失败：check_dns 返回 false，上传也失败
+    pub fn fail_dns(&self, domain: &str) {
+        self.script().dns_failures.insert(domain.to_string());
+    }
+
+    // 每次上传和请求前的延迟
+    pub fn set_latency(&self, latency: Duration) {
+        self.script().latency = latency;
+    }
+
+    // 接下来 count 次下发的 STS 已过期，使用它上传会返回 403
+    pub fn expire_next_sts(&self, count: usize) {
+        self.script().expired_sts = count;
+    }
+
+    // check_network 依次返回的网络类型，如 ["wifi", "none"] 模拟断网
+    pub fn set_network_types(&self, types: &[&str]) {
+        self.script().network_types = types.iter().map(|t| t.to_string()).collect();
+    }
+
+    // 按顺序记录的上传域名
+    pub fn attempted_domains(&self) -> Vec<String> {
+        self.script().attempts.clone()
+    }
+
+    pub fn assert_attempted(&self, expected: &[&str]) {
+        let attempts = self.attempted_domains();
+        assert_eq!(attempts, expected, "unexpected upload attempts: {:?}", attempts);
+    }
+
+    pub fn sts_requests(&self) -> usize {
+        self.script().sts_requests
+    }
+
+    // 表单上传在编排的故障之后交给 handler，默认直接成功
+    pub fn on_upload(&self, handler: impl Fn(&UploadArgs) -> XResult<()> + Send + Sync + 'static) {
+        self.script().upload_handler = Some(Arc::new(handler));
+    }
+
+    // 未设置时 upload_part 返回 UnsupportedOperation
+    pub fn on_upload_part(&self, handler: impl Fn(&UploadPartArgs) -> XResult<Value> + Send + Sync + 'static) {
+        self.script().upload_part_handler = Some(Arc::new(handler));
+    }
+
+    // 未设置时 download_file 返回 UnsupportedOperation
+    pub fn on_download(&self, handler: impl Fn(&DownloadArgs) -> XResult<()> + Send + Sync + 'static) {
+        self.script().download_handler = Some(Arc::new(handler));
+    }
+
+    // 未设置时只响应 /api/cloud/sts，其余请求返回 NotFound
+    pub fn on_request(&self, handler: impl Fn(&RequestArgs) -> XResult<Value> + Send + Sync + 'static) {
+        self.script().request_handler = Some(Arc::new(handler));
+    }
+
+    pub fn uploads(&self) -> Vec<UploadArgs> {
+        self.script().uploads.clone()
+    }
+
+    pub fn parts(&self) -> Vec<UploadPartArgs> {
+        self.script().parts.clone()
+    }
+
+    pub fn downloads(&self) -> Vec<DownloadArgs> {
+        self.script().downloads.clone()
+    }
+
+    pub fn requests(&self) -> Vec<RequestArgs> {
+        self.script().requests.clone()
+    }
+
+    pub fn storage_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.script().storage.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    async fn delay(&self) {
+        let latency = self.script().latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+    }
+}
+
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+#[async_trait]
+impl Native for ScriptedNative {
+    async fn upload_file(&self, args: UploadArgs) -> XResult<()> {
+        self.delay().await;
+        let domain = host(&args.url).to_string();
+        let mut script = self.script();
+        script.attempts.push(domain.clone());
+        if script.dns_failures.contains(&domain) {
+            return Err(XError::NetworkError(format!("dns lookup failed: {}", domain)));
+        }
+        if let Some(remaining) = script.upload_failures.get_mut(&domain).filter(|n| **n > 0) {
+            *remaining = remaining.saturating_sub(if *remaining == usize::MAX { 0 } else { 1 });
+            return Err(XError::NetworkError(format!("connection reset: {}", domain)));
+        }
+        if args.form_data["token"].as_str().is_some_and(|token| script.expired_tokens.contains(token)) {
+            return Err(XError::RequestFailed("403 ExpiredToken".to_string()));
+        }
+        let handler = script.upload_handler.clone();
+        drop(script);
+        if let Some(handler) = handler {
+            handler(&args)?;
+        }
+        self.script().uploads.push(args);
+        Ok(())
+    }
+
+    async fn upload_part(&self, args: UploadPartArgs) -> XResult<Value> {
+        self.delay().await;
+        let handler = self.script().upload_part_handler.clone().ok_or_else(|| XError::UnsupportedOperation {
+            cloud: "native".to_string(),
+            op: "upload_part".to_string(),
+        })?;
+        let res = handler(&args)?;
+        self.script().parts.push(args);
+        Ok(res)
+    }
+
+    async fn download_file(&self, args: DownloadArgs) -> XResult<()> {
+        self.delay().await;
+        let handler = self.script().download_handler.clone().ok_or_else(|| XError::UnsupportedOperation {
+            cloud: "native".to_string(),
+            op: "download_file".to_string(),
+        })?;
+        handler(&args)?;
+        self.script().downloads.push(args);
+        Ok(())
+    }
+
+    async fn request(&self, args: RequestArgs) -> XResult<Value> {
+        self.delay().await;
+        let handler = self.script().request_handler.clone();
+        if let Some(handler) = handler {
+            let res = handler(&args)?;
+            self.script().requests.push(args);
+            return Ok(res);
+        }
+        if !args.url.starts_with("/api/cloud/sts") {
+            return Err(XError::NotFound(args.url));
+        }
+        let mut script = self.script();
+        script.sts_requests += 1;
+        let token = format!("sts-{}", script.sts_requests);
+        let now = chrono::Utc::now().timestamp();
+        let expire_at = if script.expired_sts > 0 {
+            script.expired_sts -= 1;
+            script.expired_tokens.insert(token.clone());
+            now - 1
+        } else {
+            now + 3600
+        };
+        // 同时带上临时密钥，供签名地址使用
+        Ok(serde_json::json!({
+            "token": token,
+            "expireAt": expire_at,
+            "credentials": { "tmpSecretId": "id", "tmpSecretKey": "key" },
+        }))
+    }
+
+    fn set_storage(&self, key: &str, value: Value) {
+        self.script().storage.insert(key.to_string(), value);
+    }
+
+    fn get_storage(&self, key: &str) -> Option<Value> {
+        self.script().storage.get(key).cloned()
+    }
+
+    fn del_storage(&self, key: &str) {
+        self.script().storage.remove(key);
+    }
+
+    fn resolve_fallback(&self, bucket: &str, key: &str) -> String {
+        format!("https://fallback.invalid/{}/{}", bucket, key)
+    }
+
+    async fn check_network(&self) -> XResult<NetworkInfo> {
+        let mut script = self.script();
+        let network_type = if script.network_types.len() > 1 {
+            script.network_types.pop_front()
+        } else {
+            script.network_types.front().cloned()
+        }.unwrap_or_else(|| "wifi".to_string());
+        Ok(NetworkInfo {
+            has_system_proxy: false,
+            signal_strength: if network_type == "none" { 0 } else { 100 },
+            dns_error: false,
+            check_error: None,
+            network_type,
+        })
+    }
+
+    async fn check_dns(&self, domain: &str) -> XResult<bool> {
+        self.delay().await;
+        Ok(!self.script().dns_failures.contains(domain))
+    }
+}
+
+// 把 data 写入临时文件，返回 bucket（BucketSource 的 json 配置）和上传到 key 的 UploadOpts；
+// 文件名取 key 的最后一段，测试结束后由调用方删除 opts.file_path
+pub fn upload_fixture(name: &str, data: &[u8], bucket: Value, key: &str) -> (BucketSource, UploadOpts) {
+    let bucket_source: BucketSource = serde_json::from_value(bucket).expect("invalid bucket source");
+    let path = std::env::temp_dir()
+        .join(format!("xclouder-{}-{}-{}", bucket_source.cloud.as_deref().unwrap_or("fixture"), std::process::id(), name))
+        .to_string_lossy()
+        .to_string();
+    std::fs::write(&path, data).expect("write fixture");
+    let opts = UploadOpts {
+        bucket_source: Arc::new(bucket_source.clone()),
+        bucket: bucket_source.name.clone(),
+        filename: key.rsplit('/').next().unwrap_or(key).to_string(),
+        file_path: path,
+        key: key.to_string(),
+        on_progress: None,
+        up_id: 0,
+        disable_retry: false,
+        manual_retry: false,
+    };
+    (bucket_source, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clouder, ClouderOptions, UploadOptions};
+
+    fn setup(native: &Arc<ScriptedNative>, store: &Arc<MemoryStore>) -> Clouder {
+        let clouder = Clouder::new(ClouderOptions::from_native(vec![Box::new(MemoryStrategy::new("mem", store.clone()))], native.clone()));
+        clouder.init(None, serde_json::json!({
+            "cloudSource": [{
+                "name": "_mem",
+                "cloud": "mem",
+                "buckets": [{ "name": "img", "domain": "a.mem.test", "fallback": "_backup" }]
+            }, {
+                "name": "_backup",
+                "cloud": "mem",
+                "grayscale": 0,
+                "buckets": [{ "name": "img", "domain": "b.mem.test" }]
+            }],
+            "cloudMagics": []
+        })).unwrap();
+        clouder
+    }
+
+    fn opts() -> UploadOptions {
+        UploadOptions {
+            cloud_name: Some("_mem".to_string()),
+            on_progress: None,
+            disable_retry: false,
+            manual_retry: false,
+            openid: None,
+            dedup: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_switch_domain_after_failures() {
+        let (native, store) = (ScriptedNative::new(), MemoryStore::new());
+        let clouder = setup(&native, &store);
+        native.fail_uploads("a.mem.test", usize::MAX);
+        let (_, fixture) = upload_fixture("switch", b"hello", serde_json::json!({ "name": "img", "cloud": "mem" }), "a.jpg");
+        let path = fixture.file_path;
+
+        let url = clouder.upload("img", &path, Some("a.jpg".to_string()), opts()).await.unwrap();
+        assert!(url.starts_with("https://b.mem.test/_mem/"));
+        native.assert_attempted(&["a.mem.test", "a.mem.test", "a.mem.test", "a.mem.test", "b.mem.test"]);
+        assert_eq!(store.keys("img").len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_dns_failure_blocks_switch() {
+        let (native, store) = (ScriptedNative::new(), MemoryStore::new());
+        let clouder = setup(&native, &store);
+        native.fail_uploads("a.mem.test", usize::MAX);
+        native.fail_dns("b.mem.test");
+        let (_, fixture) = upload_fixture("dns", b"hello", serde_json::json!({ "name": "img", "cloud": "mem" }), "a.jpg");
+        let path = fixture.file_path;
+
+        assert!(clouder.upload("img", &path, None, opts()).await.is_err());
+        assert_eq!(native.attempted_domains().len(), 6);
+        assert!(native.attempted_domains().iter().all(|domain| domain == "a.mem.test"));
+        assert!(store.keys("img").is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_network_lost() {
+        let (native, store) = (ScriptedNative::new(), MemoryStore::new());
+        let clouder = setup(&native, &store);
+        native.fail_uploads("a.mem.test", usize::MAX);
+        native.set_network_types(&["wifi", "none"]);
+        let (_, fixture) = upload_fixture("network", b"hello", serde_json::json!({ "name": "img", "cloud": "mem" }), "a.jpg");
+        let path = fixture.file_path;
+
+        assert!(clouder.upload("img", &path, None, opts()).await.is_err());
+        native.assert_attempted(&["a.mem.test", "a.mem.test"]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_expired_sts_is_refreshed() {
+        let (native, store) = (ScriptedNative::new(), MemoryStore::new());
+        let clouder = setup(&native, &store);
+        native.expire_next_sts(1);
+        let (_, fixture) = upload_fixture("sts", b"hello", serde_json::json!({ "name": "img", "cloud": "mem" }), "a.jpg");
+        let path = fixture.file_path;
+
+        clouder.upload("img", &path, None, opts()).await.unwrap();
+        native.assert_attempted(&["a.mem.test", "a.mem.test"]);
+        assert_eq!(native.sts_requests(), 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_latency() {
+        let (native, store) = (ScriptedNative::new(), MemoryStore::new());
+        let clouder = setup(&native, &store);
+        native.set_latency(Duration::from_secs(2));
+        native.fail_uploads("a.mem.test", 1);
+        let (_, fixture) = upload_fixture("latency", b"hello", serde_json::json!({ "name": "img", "cloud": "mem" }), "a.jpg");
+        let path = fixture.file_path;
+
+        let started = tokio::time::Instant::now();
+        clouder.upload("img", &path, None, opts()).await.unwrap();
+        // STS 请求一次，上传两次
+        assert_eq!(started.elapsed(), Duration::from_secs(6));
+        native.assert_attempted(&["a.mem.test", "a.mem.test"]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
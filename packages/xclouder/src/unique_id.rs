@@ -0,0 +1,113 @@
+use std::sync::{Mutex, OnceLock};
+use rand::Rng;
+
+// Crockford Base32，编码结果按字典序即按时间排序
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+// 128 位 ID: 48 位毫秒时间戳 | 16 位节点 ID | 64 位随机序列
+// 同一毫秒内随机序列单调递增，不同进程 / 设备依靠节点 ID 和随机数避免冲突
+pub struct IdGenerator {
+    node_id: u16,
+    state: Mutex<(u64, u64)>,
+}
+
+impl IdGenerator {
+    pub fn new(node_id: Option<u16>) -> Self {
+        Self {
+            node_id: node_id.unwrap_or_else(|| rand::thread_rng().gen()),
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    pub fn node_id(&self) -> u16 {
+        self.node_id
+    }
+
+    pub fn next_id(&self) -> String {
+        let now = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let (last_ms, last_seq) = *state;
+
+        // 时钟回拨时沿用上一次的时间戳，保证单调
+        let (ms, seq) = if now <= last_ms {
+            match last_seq.checked_add(1) {
+                Some(seq) => (last_ms, seq),
+                None => (last_ms + 1, rand::thread_rng().gen::<u64>() >> 1),
+            }
+        } else {
+            // 最高位留空，给同一毫秒内的递增留出空间
+            (now, rand::thread_rng().gen::<u64>() >> 1)
+        };
+        *state = (ms, seq);
+        drop(state);
+
+        let value = ((ms as u128 & 0xFFFF_FFFF_FFFF) << 80) | ((self.node_id as u128) << 64) | seq as u128;
+        encode(value)
+    }
+}
+
+fn encode(mut value: u128) -> String {
+    let mut out = [0u8; 26];
+    for slot in out.iter_mut().rev() {
+        *slot = ALPHABET[(value & 0x1F) as usize];
+        value >>= 5;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+static GENERATOR: OnceLock<IdGenerator> = OnceLock::new();
+
+// 在第一次生成 ID 前设置节点 ID，返回是否设置成功
+pub fn set_node_id(node_id: u16) -> bool {
+    GENERATOR.set(IdGenerator::new(Some(node_id))).is_ok()
+}
+
+pub fn next_id() -> String {
+    GENERATOR.get_or_init(|| IdGenerator::new(None)).next_id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sortable() {
+        let generator = IdGenerator::new(Some(1));
+        let ids: Vec<String> = (0..1000).map(|_| generator.next_id()).collect();
+        assert!(ids.iter().all(|id| id.len() == 26));
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        // 模拟多个进程（各自的生成器，可能共用节点 ID）在多个线程中并发生成
+        #[test]
+        fn test_no_collisions(nodes in prop::collection::vec(0u16..4, 2..5), threads in 2usize..6, per_thread in 100usize..500) {
+            let generators: Vec<Arc<IdGenerator>> = nodes.iter()
+                .map(|node| Arc::new(IdGenerator::new(Some(*node))))
+                .collect();
+
+            let handles: Vec<_> = generators.iter()
+                .flat_map(|generator| (0..threads).map(move |_| generator.clone()))
+                .map(|generator| std::thread::spawn(move || {
+                    (0..per_thread).map(|_| generator.next_id()).collect::<Vec<_>>()
+                }))
+                .collect();
+
+            let mut seen = HashSet::new();
+            for handle in handles {
+                for id in handle.join().unwrap() {
+                    prop_assert!(seen.insert(id));
+                }
+            }
+            prop_assert_eq!(seen.len(), nodes.len() * threads * per_thread);
+        }
+    }
+}
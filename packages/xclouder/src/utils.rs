@@ -1,20 +1,11 @@
-use chrono::{DateTime, Utc};
-use rand::Rng;
+use md5::{Digest, Md5};
+use crate::error::{XError, XResult};
+use crate::unique_id;
+use crate::xkey::XKey;
 
+// 全局唯一且按时间排序的 ID，见 unique_id
 pub fn shot_unique() -> String {
-    let base_time = 1732636800; // 2024-11-27 00:00:00
-    let now = Utc::now().timestamp();
-    let rel_now = now - base_time;
-    let rand_num = rand::thread_rng().gen_range(0..10);
-    let id = (rel_now * 100 + rand_num) * 10000 + get_counter();
-    format!("{:x}", id)
-}
-
-// 计数器，用于生成唯一ID
-static COUNTER: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
-
-fn get_counter() -> i64 {
-    COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst) as i64 % 10000
+    unique_id::next_id()
 }
 
 pub fn fill_name(file_path: &str, openid: &str) -> String {
@@ -23,12 +14,15 @@ pub fn fill_name(file_path: &str, openid: &str) -> String {
 }
 
 pub fn fix_name(file_path: &str) -> String {
-    file_path.replace("_tos", "_fix")
-        .replace("_cos", "_fix")
-        .replace("_oss", "_fix")
+    match XKey::parse(file_path) {
+        Ok(xkey) if xkey.is_xclouder() => xkey.with_cloud("_fix")
+            .map(|xkey| xkey.to_string())
+            .unwrap_or_else(|_| file_path.to_string()),
+        _ => file_path.to_string(),
+    }
 }
 
-fn extract_ext(file_path: &str) -> String {
+pub fn extract_ext(file_path: &str) -> String {
     if let Some(dot_pos) = file_path.rfind('.') {
         file_path[dot_pos..].to_string()
     } else {
@@ -36,6 +30,21 @@ fn extract_ext(file_path: &str) -> String {
     }
 }
 
+pub fn file_md5(file_path: &str) -> XResult<String> {
+    let mut file = std::fs::File::open(file_path)
+        .map_err(|e| XError::UploadFailed(format!("read {}: {}", file_path, e)))?;
+    let mut hasher = Md5::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| XError::UploadFailed(format!("read {}: {}", file_path, e)))?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// 分片上传的 ETag 不是内容 MD5，不参与比对
+pub fn plain_md5(etag: Option<&str>) -> Option<String> {
+    etag.filter(|etag| etag.len() == 32 && etag.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|etag| etag.to_lowercase())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,6 +76,7 @@ mod tests {
         assert_eq!(fix_name("_tos/test.jpg"), "_fix/test.jpg");
         assert_eq!(fix_name("_oss/test.jpg"), "_fix/test.jpg");
         assert_eq!(fix_name("test.jpg"), "test.jpg");
+        assert_eq!(fix_name("_cos/a_tos.jpg"), "_fix/a_tos.jpg");
     }
 
     #[test]
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::error::{XError, XResult};
+
+// 旧格式 key 没有命中任何规则时归属的云源
+pub const DEFAULT_LEGACY_CLOUD: &str = "_cos";
+
+// 旧格式 key 的归属规则，按配置顺序匹配前缀
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LegacyRule {
+    pub prefix: String,
+    #[serde(rename = "cloudName")]
+    pub cloud_name: String,
+}
+
+// xclouder key: _cloud/owner/name.ext?query
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XKey {
+    cloud: Option<String>,
+    path: String,
+    query: Option<String>,
+}
+
+fn is_cloud_name(name: &str) -> bool {
+    name.len() > 1
+        && name.starts_with('_')
+        && name[1..].chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+impl XKey {
+    pub fn new(cloud: Option<&str>, path: &str) -> XResult<Self> {
+        if let Some(cloud) = cloud {
+            if !is_cloud_name(cloud) {
+                return Err(XError::InvalidKey(format!("invalid cloud name {}", cloud)));
+            }
+        }
+        if path.is_empty() || path.ends_with('/') {
+            return Err(XError::InvalidKey(format!("empty object name in {}", path)));
+        }
+        if path.split('/').any(|seg| seg.is_empty() || seg == "." || seg == "..") {
+            return Err(XError::InvalidKey(format!("invalid path {}", path)));
+        }
+        Ok(Self {
+            cloud: cloud.map(|c| c.to_string()),
+            path: path.to_string(),
+            query: None,
+        })
+    }
+
+    pub fn parse(key: &str) -> XResult<Self> {
+        let key = key.strip_prefix('/').unwrap_or(key);
+        let (key, query) = match key.split_once('?') {
+            Some((key, query)) => (key, Some(query)),
+            None => (key, None),
+        };
+
+        let mut xkey = match key.split_once('/') {
+            Some((cloud, path)) if cloud.starts_with('_') => Self::new(Some(cloud), path)?,
+            _ => Self::new(None, key)?,
+        };
+        xkey.query = query.filter(|q| !q.is_empty()).map(|q| q.to_string());
+        Ok(xkey)
+    }
+
+    pub fn is_xclouder(&self) -> bool {
+        self.cloud.is_some()
+    }
+
+    pub fn cloud(&self) -> Option<&str> {
+        self.cloud.as_deref()
+    }
+
+    // 旧格式 key 按规则推断云源
+    pub fn cloud_name<'a>(&'a self, rules: &'a [LegacyRule]) -> &'a str {
+        if let Some(cloud) = &self.cloud {
+            return cloud;
+        }
+        rules.iter()
+            .find(|rule| self.path.starts_with(&rule.prefix))
+            .map(|rule| rule.cloud_name.as_str())
+            .unwrap_or(DEFAULT_LEGACY_CLOUD)
+    }
+
+    // 不带云源前缀的路径
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn owner(&self) -> Option<&str> {
+        self.path.rsplit_once('/').map(|(owner, _)| owner)
+    }
+
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    pub fn ext(&self) -> Option<&str> {
+        let name = self.name();
+        name.rfind('.').map(|idx| &name[idx..])
+    }
+
+    // 存储上的对象 key，不带查询参数
+    pub fn object_key(&self) -> String {
+        match &self.cloud {
+            Some(cloud) => format!("{}/{}", cloud, self.path),
+            None => self.path.clone(),
+        }
+    }
+
+    // 去掉云源前缀，保留查询参数
+    pub fn simple(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
+
+    pub fn with_cloud(&self, cloud: &str) -> XResult<Self> {
+        let mut xkey = Self::new(Some(cloud), &self.path)?;
+        xkey.query = self.query.clone();
+        Ok(xkey)
+    }
+
+    pub fn with_query(mut self, query: Option<&str>) -> Self {
+        self.query = query.filter(|q| !q.is_empty()).map(|q| q.to_string());
+        self
+    }
+}
+
+// 列举用的前缀可能只到目录，不能按完整 key 校验
+pub fn prefix_cloud_name<'a>(prefix: &'a str, rules: &'a [LegacyRule]) -> &'a str {
+    let prefix = prefix.strip_prefix('/').unwrap_or(prefix);
+    match prefix.split_once('/') {
+        Some((cloud, _)) if is_cloud_name(cloud) => cloud,
+        _ => rules.iter()
+            .find(|rule| prefix.starts_with(&rule.prefix))
+            .map(|rule| rule.cloud_name.as_str())
+            .unwrap_or(DEFAULT_LEGACY_CLOUD),
+    }
+}
+
+impl fmt::Display for XKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.object_key())?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for XKey {
+    type Err = XError;
+
+    fn from_str(s: &str) -> XResult<Self> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let key = XKey::parse("/_cos/openid/abc.jpg?imageMogr2/thumbnail/200").unwrap();
+        assert_eq!(key.cloud(), Some("_cos"));
+        assert_eq!(key.owner(), Some("openid"));
+        assert_eq!(key.name(), "abc.jpg");
+        assert_eq!(key.ext(), Some(".jpg"));
+        assert_eq!(key.object_key(), "_cos/openid/abc.jpg");
+        assert_eq!(key.simple(), "openid/abc.jpg?imageMogr2/thumbnail/200");
+        assert_eq!(key.to_string(), "_cos/openid/abc.jpg?imageMogr2/thumbnail/200");
+
+        let key = XKey::parse("cos-upload/abc.jpg").unwrap();
+        assert!(!key.is_xclouder());
+        assert_eq!(key.object_key(), "cos-upload/abc.jpg");
+
+        assert!(XKey::parse("").is_err());
+        assert!(XKey::parse("_cos/").is_err());
+        assert!(XKey::parse("_cos/../a.jpg").is_err());
+        assert!(XKey::parse("_c$s/a.jpg").is_err());
+    }
+
+    #[test]
+    fn test_legacy_rules() {
+        let rules = vec![
+            LegacyRule { prefix: "video/".to_string(), cloud_name: "_tos".to_string() },
+            LegacyRule { prefix: "album/".to_string(), cloud_name: "_oss".to_string() },
+        ];
+        assert_eq!(XKey::parse("video/a.mp4").unwrap().cloud_name(&rules), "_tos");
+        assert_eq!(XKey::parse("album/a.jpg").unwrap().cloud_name(&rules), "_oss");
+        // 不再按子串猜测，路径中包含 cos 不会影响归属
+        assert_eq!(XKey::parse("photos/tos.jpg").unwrap().cloud_name(&rules), DEFAULT_LEGACY_CLOUD);
+        assert_eq!(XKey::parse("_tos2/tos.jpg").unwrap().cloud_name(&rules), "_tos2");
+    }
+
+    #[test]
+    fn test_example_legacy_rules() {
+        let config: crate::config::Config = serde_json::from_str(include_str!("../../../config.example.json")).unwrap();
+        // 旧版按子串猜测的归属，已有的旧 key 都以云厂商命名的目录开头
+        let substring_cloud = |key: &str| {
+            if key.contains("cos") {
+                "_cos"
+            } else if key.contains("tos") {
+                "_tos"
+            } else if key.contains("oss") {
+                "_oss"
+            } else {
+                "_cos"
+            }
+        };
+        for key in ["cos-upload/abc.jpg", "tos-upload/abc.mp4", "oss-upload/abc.jpg", "tos/openid/abc.jpg", "oss_record/abc.mp3", "upload/abc.jpg"] {
+            let xkey = XKey::parse(key).unwrap();
+            assert_eq!(xkey.cloud_name(&config.legacy_rules), substring_cloud(key), "{}", key);
+            assert_eq!(prefix_cloud_name(key, &config.legacy_rules), substring_cloud(key), "{}", key);
+        }
+        // 文件名里碰巧带 cos 的不再被误判
+        assert_eq!(XKey::parse("tos-upload/cosplay.jpg").unwrap().cloud_name(&config.legacy_rules), "_tos");
+    }
+}